@@ -0,0 +1,361 @@
+use crate::THRESHOLD;
+use crate::eval::Env;
+use crate::format::{RateFormat, format_expr, format_float};
+use crate::model::NodeMeta;
+use crate::solver::{Constraint, LpResult, Relation, maximize, maximize_integer, solve_linear};
+use crate::stats::{ChartStats, machine_count};
+use egui_snarl::{NodeId, Snarl};
+use std::fmt::Write;
+use std::{collections::HashMap, ops::Not};
+
+/// Weight of the number of machines against the idle capacity in `tight_integer`.
+const MACHINE_COST: f64 = 1E-3;
+
+/// How each process affects each resource it's wired to.
+pub struct Flows {
+    /// Processes whose activity is to be determined.
+    pub free: Vec<NodeId>,
+    pub resources: Vec<ResourceFlow>,
+}
+
+pub struct ResourceFlow {
+    pub node: NodeId,
+    /// Rate contributed by locked processes and the base rate.
+    pub fixed: f64,
+    /// Rate per unit of activity of each free process.
+    pub coefs: Vec<f64>,
+    pub has_base_rate: bool,
+    pub produced: bool,
+    pub consumed: bool,
+}
+
+impl ResourceFlow {
+    /// Resources count as intermediate if they're both produced and consumed, or have a base rate.
+    pub fn is_intermediate(&self) -> bool { self.has_base_rate || self.produced && self.consumed }
+    pub fn is_raw(&self) -> bool { self.consumed && !self.produced }
+}
+
+/// Label of a node for messages, with a placeholder if it has none.
+pub fn label(chart: &Snarl<NodeMeta>, node: NodeId) -> &str {
+    let label = chart[node].label();
+    if label.is_empty() { "(unnamed)" } else { label }
+}
+
+impl Flows {
+    /// Resources with an invalid base rate are left out.
+    pub fn collect(chart: &Snarl<NodeMeta>, stats: &ChartStats) -> Self {
+        let mut free = Vec::new();
+        let mut fixed = HashMap::<NodeId, f64>::new();
+        let mut per_unit = HashMap::<(NodeId, NodeId), f64>::new();
+        let mut produced = HashMap::<NodeId, (bool, bool)>::new();
+        let power = Vec::from_iter(chart.node_ids().filter(|x| matches!(x.1, NodeMeta::Resource(x) if x.power)).map(|x| x.0));
+        for (node, meta) in chart.node_ids() {
+            // Subfactories count as locked.
+            let locked = match meta {
+                NodeMeta::Resource(_) => continue,
+                NodeMeta::Process(meta) => meta.locked,
+                NodeMeta::Subfactory(_) => true,
+            };
+            let values = &stats.process(node).values;
+            let Some(speed) = values.speed else { continue };
+            let rate = locked.then(|| values.common_rate().map(|x| x.0)).flatten();
+            if locked && rate.is_none() {
+                continue;
+            }
+            let mut add = |r: NodeId, qty: f64| {
+                if let Some(rate) = rate {
+                    *fixed.entry(r).or_default() += rate * qty;
+                } else {
+                    *per_unit.entry((r, node)).or_default() += speed * qty;
+                }
+                let flags = produced.entry(r).or_default();
+                if qty > 0. { flags.0 = true } else { flags.1 = true }
+            };
+            values.flows().for_each(|(r, qty)| add(r, qty));
+            // Power is drawn by idle machines too, so it's not proportional to activity.
+            for &r in &power {
+                let (Some(active), Some(idle), Some(capacity)) = (values.power, values.idle_power, values.capacity) else { continue };
+                if locked {
+                    *fixed.entry(r).or_default() -= values.power_draw().unwrap_or(0.);
+                } else {
+                    *per_unit.entry((r, node)).or_default() -= active - idle;
+                    *fixed.entry(r).or_default() -= idle * capacity;
+                }
+                (active != 0. || idle != 0.).then(|| produced.entry(r).or_default().1 = true);
+            }
+            if !locked {
+                free.push(node);
+            }
+        }
+        let mut resources = Vec::new();
+        for (node, meta) in chart.node_ids() {
+            let NodeMeta::Resource(meta) = meta else { continue };
+            let resource = stats.resource(node);
+            let false = resource.invalid else { continue };
+            let rate = fixed.get(&node).copied().unwrap_or(0.) + resource.base_rate;
+            let coefs = Vec::from_iter(free.iter().map(|&p| per_unit.get(&(node, p)).copied().unwrap_or(0.)));
+            let (produced, consumed) = produced.get(&node).copied().unwrap_or_default();
+            resources.push(ResourceFlow { node, fixed: rate, coefs, has_base_rate: meta.use_base_rate, produced, consumed });
+        }
+        Self { free, resources }
+    }
+
+    pub fn current_activity(stats: &ChartStats, node: NodeId) -> f64 { stats.process(node).values.activity.unwrap_or(0.) }
+
+    fn apply(&self, chart: &mut Snarl<NodeMeta>, activities: &[f64]) {
+        for (&node, &activity) in self.free.iter().zip(activities) {
+            let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
+            meta.activity = format_expr(activity, THRESHOLD);
+        }
+    }
+}
+
+/// Set the activity of every unlocked process so that all intermediate resources have zero net rate.
+/// Return a human-readable report.
+pub fn balance_all(chart: &mut Snarl<NodeMeta>, env: &Env) -> String {
+    let stats = ChartStats::compute(chart, env);
+    let flows = Flows::collect(chart, &stats);
+    let true = !flows.free.is_empty() else { return "There are no unlocked processes to balance.".to_owned() };
+    let initial = Vec::from_iter(flows.free.iter().map(|&p| Flows::current_activity(&stats, p)));
+    let constrained = Vec::from_iter(flows.resources.iter().filter(|x| x.is_intermediate()));
+    let rows = Vec::from_iter(constrained.iter().map(|x| x.coefs.clone()));
+    let rhs = Vec::from_iter(constrained.iter().map(|x| -x.fixed));
+    let solution = solve_linear(rows, rhs, &initial);
+    let mut report = format!("Balanced {} resources using {} processes.", constrained.len(), flows.free.len());
+    if !solution.free.is_empty() {
+        _ = write!(report, "\nUnder-determined: {} degrees of freedom; kept the current activity of:", solution.free.len());
+        solution.free.iter().for_each(|&i| _ = write!(report, "\n    {}", label(chart, flows.free[i])));
+    }
+    if !solution.inconsistent.is_empty() {
+        _ = write!(report, "\nOver-determined: these resources can't be balanced together with the rest:");
+        solution.inconsistent.iter().for_each(|&i| _ = write!(report, "\n    {}", label(chart, constrained[i].node)));
+    }
+    for (&node, &activity) in flows.free.iter().zip(&solution.values) {
+        if activity < -THRESHOLD {
+            _ = write!(report, "\nWarning: negative activity for {}", label(chart, node));
+        }
+    }
+    flows.apply(chart, &solution.values);
+    report
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Objective {
+    MaximizeResource(NodeId),
+    MinimizeMachines,
+    MinimizeRawDraw,
+}
+
+/// Choose the activity of every unlocked process to optimize `objective`, keeping activities within capacities
+/// and resources marked as non-negative from running out. Return a human-readable report.
+pub fn optimize(chart: &mut Snarl<NodeMeta>, env: &Env, fmt: RateFormat, objective: Objective) -> String {
+    let stats = ChartStats::compute(chart, env);
+    let flows = Flows::collect(chart, &stats);
+    let true = !flows.free.is_empty() else { return "There are no unlocked processes to optimize.".to_owned() };
+    let n = flows.free.len();
+    let mut constraints = Vec::new();
+    for (i, &node) in flows.free.iter().enumerate() {
+        let Some(capacity) = stats.process(node).values.capacity else { return format!("Invalid capacity for {}", label(chart, node)) };
+        let mut coefs = vec![0.; n];
+        coefs[i] = 1.;
+        constraints.push(Constraint { coefs, relation: Relation::Le, rhs: capacity });
+    }
+    for flow in &flows.resources {
+        let NodeMeta::Resource(meta) = &chart[flow.node] else { unreachable!() };
+        let true = meta.nonnegative else { continue };
+        constraints.push(Constraint { coefs: flow.coefs.clone(), relation: Relation::Ge, rhs: -flow.fixed });
+    }
+    let coefs = match objective {
+        Objective::MaximizeResource(r) => {
+            let Some(flow) = flows.resources.iter().find(|x| x.node == r) else { return "Invalid objective resource".to_owned() };
+            flow.coefs.clone()
+        }
+        Objective::MinimizeMachines => vec![-1.; n],
+        Objective::MinimizeRawDraw => flows.resources.iter().filter(|x| x.is_raw()).fold(vec![0.; n], |mut acc, x| {
+            acc.iter_mut().zip(&x.coefs).for_each(|(a, b)| *a += b);
+            acc
+        }),
+    };
+    let activities = match maximize(&coefs, &constraints) {
+        LpResult::Optimal(x) => x,
+        LpResult::Infeasible => return "Infeasible: the non-negative resources can't be satisfied within the capacities.".to_owned(),
+        LpResult::Unbounded => return "Unbounded: the objective can be improved indefinitely.".to_owned(),
+    };
+    flows.apply(chart, &activities);
+    let stats = ChartStats::compute(chart, env);
+    let mut report = match objective {
+        Objective::MaximizeResource(r) => format!("Net rate of {}: {}", label(chart, r), fmt.format(stats.resource(r).net)),
+        Objective::MinimizeMachines => format!("Total activity: {}", format_float(activities.iter().sum(), THRESHOLD)),
+        Objective::MinimizeRawDraw => {
+            let draw = flows.resources.iter().filter(|x| x.is_raw()).map(|x| stats.resource(x.node).dec).sum();
+            format!("Raw resource draw: {}", fmt.format(draw))
+        }
+    };
+    report += "\n\nActivities:";
+    for (&node, &activity) in flows.free.iter().zip(&activities) {
+        let NodeMeta::Process(meta) = &chart[node] else { unreachable!() };
+        _ = write!(report, "\n    {}: {} / {}", label(chart, node), format_float(activity, THRESHOLD), meta.capacity);
+    }
+    let mut verified = true;
+    for (node, meta) in chart.node_ids() {
+        let NodeMeta::Resource(meta) = meta else { continue };
+        let true = meta.nonnegative else { continue };
+        let stats = stats.resource(node);
+        if stats.invalid || stats.net < -THRESHOLD {
+            verified = false;
+            _ = write!(report, "\nViolated: {} has net rate {}", label(chart, node), fmt.format(stats.net));
+        }
+    }
+    verified.then(|| report += "\n\nVerified: all non-negative resources are satisfied.");
+    report
+}
+
+/// Set the capacity of every process to the minimum number of whole machines covering its activity.
+pub fn round_up_capacities(chart: &mut Snarl<NodeMeta>, env: &Env) {
+    let stats = ChartStats::compute(chart, env);
+    for (node, meta) in chart.nodes_ids_mut() {
+        let NodeMeta::Process(meta) = meta else { continue };
+        let true = !meta.activity.is_empty() else { continue };
+        let Some(activity) = stats.process(node).values.activity else { continue };
+        meta.capacity = machine_count(activity).to_string();
+    }
+}
+
+/// Search for whole machine counts and activities of the unlocked processes that keep intermediate resources balanced
+/// and run each process at least at its current activity, while minimizing the total idle capacity, then the number of machines.
+/// Return a human-readable report.
+pub fn tight_integer(chart: &mut Snarl<NodeMeta>, env: &Env) -> String {
+    let stats = ChartStats::compute(chart, env);
+    let flows = Flows::collect(chart, &stats);
+    let true = !flows.free.is_empty() else { return "There are no unlocked processes to search.".to_owned() };
+    let n = flows.free.len();
+    let mut constraints = Vec::new();
+    for (i, &node) in flows.free.iter().enumerate() {
+        let Some(activity) = stats.process(node).values.activity else { return format!("Invalid activity for {}", label(chart, node)) };
+        let mut coefs = vec![0.; 2 * n];
+        coefs[i] = 1.;
+        coefs[n + i] = -1.;
+        constraints.push(Constraint { coefs: coefs.clone(), relation: Relation::Le, rhs: 0. });
+        // The current activities set the demand. Without them, running nothing would leave no idle capacity.
+        coefs[n + i] = 0.;
+        constraints.push(Constraint { coefs, relation: Relation::Ge, rhs: activity });
+    }
+    for flow in flows.resources.iter().filter(|x| x.is_intermediate()) {
+        let mut coefs = flow.coefs.clone();
+        coefs.resize(2 * n, 0.);
+        constraints.push(Constraint { coefs, relation: Relation::Eq, rhs: -flow.fixed });
+    }
+    // Among plans as tight, prefer fewer machines, so that activities aren't scaled up for nothing.
+    let objective = Vec::from_iter((0..2 * n).map(|i| if i < n { 1. } else { -1. - MACHINE_COST }));
+    let integer = Vec::from_iter((0..2 * n).map(|i| i >= n));
+    let (result, finished) = maximize_integer(&objective, &constraints, &integer, 10000);
+    let LpResult::Optimal(values) = result else { return "Infeasible: the intermediate resources can't be balanced.".to_owned() };
+    let (activities, machines) = values.split_at(n);
+    let false = activities.iter().all(|x| x.abs() < THRESHOLD) else {
+        return "Every activity would be zero; set the activities to plan for first.".to_owned();
+    };
+    let mut report = format!(
+        "Total machines: {}\nIdle capacity: {}",
+        format_float(machines.iter().sum(), THRESHOLD),
+        format_float(machines.iter().zip(activities).map(|(a, b)| a - b).sum(), THRESHOLD)
+    );
+    finished.not().then(|| report += "\nThe search limit was reached; this is the best solution found.");
+    for (i, &node) in flows.free.iter().enumerate() {
+        let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
+        meta.capacity = machines[i].round().to_string();
+        meta.activity = format_expr(activities[i], THRESHOLD);
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{gear_chain, process_meta};
+    use crate::format::TimeUnit;
+
+    fn demand(chart: &mut Snarl<NodeMeta>, r: NodeId, base_rate: &str) {
+        let NodeMeta::Resource(meta) = &mut chart[r] else { unreachable!() };
+        (meta.use_base_rate, meta.base_rate) = (true, base_rate.to_owned());
+    }
+
+    #[test]
+    fn balance_all_determined() {
+        let (mut chart, [_, _, gear, smelter, assembler]) = gear_chain();
+        demand(&mut chart, gear, "-(0.1 + 0.2)");
+        let report = balance_all(&mut chart, &Env::new(&[]));
+        assert_eq!(report, "Balanced 2 resources using 2 processes.");
+        assert_eq!([smelter, assembler].map(|x| process_meta(&mut chart, x).activity.clone()), ["0.6", "0.3"]);
+    }
+
+    #[test]
+    fn balance_all_keeps_free_columns() {
+        let (mut chart, [_, _, _, smelter, assembler]) = gear_chain();
+        process_meta(&mut chart, assembler).activity = "1.5".to_owned();
+        let report = balance_all(&mut chart, &Env::new(&[]));
+        assert!(report.contains("Under-determined: 1 degrees of freedom; kept the current activity of:\n    Assembler"));
+        assert_eq!([smelter, assembler].map(|x| process_meta(&mut chart, x).activity.clone()), ["3", "1.5"]);
+    }
+
+    #[test]
+    fn balance_all_reports_inconsistent_rows_and_keeps_locked() {
+        let (mut chart, [_, _, gear, smelter, assembler]) = gear_chain();
+        demand(&mut chart, gear, "-3");
+        let meta = process_meta(&mut chart, assembler);
+        (meta.locked, meta.activity) = (true, "0.5".to_owned());
+        let report = balance_all(&mut chart, &Env::new(&[]));
+        assert!(report.starts_with("Balanced 2 resources using 1 processes."));
+        assert!(report.contains("Over-determined: these resources can't be balanced together with the rest:\n    Gear"));
+        assert_eq!([smelter, assembler].map(|x| process_meta(&mut chart, x).activity.clone()), ["1", "0.5"]);
+    }
+
+    #[test]
+    fn optimize_within_capacities() {
+        let (mut chart, [_, plate, gear, smelter, assembler]) = gear_chain();
+        let NodeMeta::Resource(meta) = &mut chart[plate] else { unreachable!() };
+        meta.nonnegative = true;
+        process_meta(&mut chart, assembler).capacity = "3".to_owned();
+        let fmt = RateFormat::new(None, TimeUnit::Second);
+        let report = optimize(&mut chart, &Env::new(&[]), fmt, Objective::MaximizeResource(gear));
+        assert!(report.ends_with("Verified: all non-negative resources are satisfied."));
+        assert_eq!([smelter, assembler].map(|x| process_meta(&mut chart, x).activity.clone()), ["2", "1"]);
+    }
+
+    #[test]
+    fn optimize_keeps_locked() {
+        let (mut chart, [_, plate, gear, smelter, assembler]) = gear_chain();
+        let NodeMeta::Resource(meta) = &mut chart[plate] else { unreachable!() };
+        meta.nonnegative = true;
+        let meta = process_meta(&mut chart, smelter);
+        (meta.locked, meta.activity) = (true, "1".to_owned());
+        let fmt = RateFormat::new(None, TimeUnit::Second);
+        let report = optimize(&mut chart, &Env::new(&[]), fmt, Objective::MaximizeResource(gear));
+        assert!(report.contains("Assembler: 500 m / 1"));
+        assert_eq!([smelter, assembler].map(|x| process_meta(&mut chart, x).activity.clone()), ["1", "0.5"]);
+        process_meta(&mut chart, assembler).capacity = "-1".to_owned();
+        let report = optimize(&mut chart, &Env::new(&[]), fmt, Objective::MaximizeResource(gear));
+        assert!(report.starts_with("Infeasible"));
+    }
+
+    #[test]
+    fn tight_integer_keeps_demand() {
+        let (mut chart, [_, plate, _, smelter, assembler]) = gear_chain();
+        process_meta(&mut chart, smelter).activity = "1.5".to_owned();
+        process_meta(&mut chart, assembler).activity = "0.75".to_owned();
+        let report = tight_integer(&mut chart, &Env::new(&[]));
+        assert_eq!(Vec::from_iter(report.lines().map(str::trim_end)), ["Total machines: 3", "Idle capacity: 0"]);
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        let [smelter, assembler] = [smelter, assembler].map(|x| &stats.process(x).values);
+        assert_eq!((smelter.capacity, assembler.capacity), (Some(2.), Some(1.)));
+        assert!((smelter.activity.unwrap() - 2.).abs() < 1E-6 && (assembler.activity.unwrap() - 1.).abs() < 1E-6);
+        assert!(stats.resource(plate).net.abs() < 1E-6);
+    }
+
+    #[test]
+    fn tight_integer_refuses_idle_plan() {
+        let (mut chart, [_, _, _, smelter, assembler]) = gear_chain();
+        [smelter, assembler].into_iter().for_each(|x| process_meta(&mut chart, x).activity = "0".to_owned());
+        assert!(tight_integer(&mut chart, &Env::new(&[])).starts_with("Every activity would be zero"));
+        assert_eq!(process_meta(&mut chart, smelter).capacity, "2");
+    }
+}
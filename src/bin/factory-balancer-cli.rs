@@ -0,0 +1,118 @@
+//! Evaluate a saved chart and report the rates without the UI.
+//! Exits with a non-zero status if any resource is unbalanced or anything is invalid.
+
+use anyhow::{Context as _, Result, bail};
+use egui_snarl::NodeId;
+use factory_balancer::THRESHOLD;
+use factory_balancer::balance::Flows;
+use factory_balancer::eval::Env;
+use factory_balancer::format::{RateFormat, TimeUnit};
+use factory_balancer::model::{NodeMeta, parse_document};
+use factory_balancer::stats::{ChartStats, ProcessStatus, ResourceStats};
+use serde_json::{Value, json};
+use std::collections::HashSet;
+use std::process::ExitCode;
+use std::{env, fs, io, io::Read};
+
+const USAGE: &str = "Usage: factory-balancer-cli [--json] <chart.ron | ->";
+
+/// Intermediate resources should have zero net rate, and non-negative ones shouldn't run out.
+fn resource_status(stats: &ResourceStats, intermediate: bool, nonnegative: bool) -> &'static str {
+    if stats.invalid {
+        "invalid"
+    } else if intermediate && stats.net.abs() > THRESHOLD || nonnegative && stats.net < -THRESHOLD {
+        "unbalanced"
+    } else {
+        "ok"
+    }
+}
+
+fn process_status(status: &ProcessStatus) -> &'static str {
+    match status {
+        ProcessStatus::Invalid => "invalid",
+        ProcessStatus::Balanced => "balanced",
+        ProcessStatus::Deficient => "deficient",
+        ProcessStatus::Excess => "excess",
+    }
+}
+
+fn print_table(rows: &[Vec<String>]) {
+    let mut widths = Vec::new();
+    for row in rows {
+        widths.resize(widths.len().max(row.len()), 0);
+        row.iter().zip(&mut widths).for_each(|(x, w)| *w = (*w).max(x.chars().count()));
+    }
+    for row in rows {
+        let cells = row.iter().zip(&widths).map(|(x, w)| format!("{x:w$}"));
+        println!("{}", Vec::from_iter(cells).join("  ").trim_end());
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let mut json = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => return Ok((println!("{USAGE}"), ExitCode::SUCCESS).1),
+            _ if path.is_none() => path = Some(arg),
+            _ => bail!("{USAGE}"),
+        }
+    }
+    let path = path.context(USAGE)?;
+    let data = if path == "-" {
+        let mut data = String::new();
+        (io::stdin().read_to_string(&mut data)?, data).1
+    } else {
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {path}"))?
+    };
+    let doc = parse_document(&data)?;
+    let env = Env::new(&doc.variables);
+    let stats = ChartStats::compute(&doc.chart, &env);
+    let flows = Flows::collect(&doc.chart, &stats);
+    let intermediate = HashSet::<NodeId>::from_iter(flows.resources.iter().filter(|x| x.is_intermediate()).map(|x| x.node));
+    let fmt = RateFormat::new(doc.time_unit, doc.time_unit.unwrap_or(TimeUnit::Second));
+    let mut ok = true;
+    let (mut resources, mut processes) = (Vec::new(), Vec::new());
+    for (node, meta) in doc.chart.node_ids() {
+        match meta {
+            NodeMeta::Resource(meta) => {
+                let r = stats.resource(node);
+                let status = resource_status(&r, intermediate.contains(&node), meta.nonnegative);
+                ok &= status == "ok";
+                resources.push((&meta.label, status, r));
+            }
+            NodeMeta::Process(_) | NodeMeta::Subfactory(_) => {
+                let label = meta.label();
+                let p = stats.process(node);
+                ok &= !matches!(p.status, ProcessStatus::Invalid);
+                processes.push((label, process_status(&p.status), p));
+            }
+        }
+    }
+    if json {
+        let resources =
+            resources.iter().map(|(label, status, r)| json!({"label": label, "status": status, "inc": r.inc, "dec": r.dec, "net": r.net}));
+        let processes = processes.iter().map(|(label, status, p)| {
+            let rate = p.values.common_rate().map(|x| x.0);
+            json!({"label": label, "status": status, "capacity": p.values.capacity, "activity": p.values.activity, "rate": rate})
+        });
+        let report = json!({"ok": ok, "resources": Value::from_iter(resources), "processes": Value::from_iter(processes)});
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        let number = |x: Option<f64>| x.map_or("?".to_owned(), |x| x.to_string());
+        let mut rows = vec![Vec::from(["Resource", "Inc", "Dec", "Net", "Status"].map(String::from))];
+        for (label, status, r) in &resources {
+            rows.push(vec![label.to_string(), fmt.format(r.inc), fmt.format(r.dec), fmt.format(r.net), status.to_string()]);
+        }
+        print_table(&rows);
+        println!();
+        let mut rows = vec![Vec::from(["Process", "Capacity", "Activity", "Rate", "Status"].map(String::from))];
+        for (label, status, p) in &processes {
+            let rate = p.values.common_rate().map_or("?".to_owned(), |x| fmt.format(x.0));
+            rows.push(vec![label.to_string(), number(p.values.capacity), number(p.values.activity), rate, status.to_string()]);
+        }
+        print_table(&rows);
+    }
+    Ok(if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
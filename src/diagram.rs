@@ -0,0 +1,96 @@
+use crate::viewer::{node_fill, resource_fill};
+use eframe::egui::epaint::CubicBezierShape;
+use eframe::egui::{Align2, Color32, CornerRadius, FontId, Rect, Sense, Stroke, Ui, pos2, vec2};
+use egui_snarl::{NodeId, Snarl};
+use factory_balancer::balance::label;
+use factory_balancer::format::RateFormat;
+use factory_balancer::model::NodeMeta;
+use factory_balancer::sankey::Sankey;
+use factory_balancer::stats::ChartStats;
+use std::collections::HashMap;
+
+const NODE_WIDTH: f32 = 12.;
+const MIN_NODE_HEIGHT: f32 = 4.;
+const GAP: f32 = 24.;
+/// Room for the labels at the sides.
+const MARGIN: f32 = 120.;
+
+/// Read-only Sankey diagram of the chart, scaled to fit the available space.
+pub fn show_sankey(ui: &mut Ui, chart: &Snarl<NodeMeta>, stats: &ChartStats, fmt: &RateFormat) {
+    let sankey = Sankey::layout(chart, stats);
+    let false = sankey.columns.is_empty() else { return drop(ui.label("(Empty)")) };
+    let rect = ui.available_rect_before_wrap();
+    ui.allocate_rect(rect, Sense::hover());
+    let painter = ui.painter_at(rect);
+
+    // Each node is as tall as the larger of its inflow and outflow.
+    let (mut inflow, mut outflow) = (HashMap::<NodeId, f64>::new(), HashMap::<NodeId, f64>::new());
+    for link in &sankey.links {
+        *outflow.entry(link.from).or_default() += link.rate.abs();
+        *inflow.entry(link.to).or_default() += link.rate.abs();
+    }
+    let flow = |node: &NodeId| inflow.get(node).copied().unwrap_or(0.).max(outflow.get(node).copied().unwrap_or(0.));
+    let scale = sankey.columns.iter().fold(f64::INFINITY, |scale, column| {
+        let total = column.iter().map(flow).sum::<f64>();
+        let room = rect.height() - GAP * (column.len() + 1) as f32 - MIN_NODE_HEIGHT * column.len() as f32;
+        if total > 0. { scale.min(room.max(0.) as f64 / total) } else { scale }
+    });
+    let scale = if scale.is_finite() { scale } else { 0. };
+
+    let spacing = (rect.width() - 2. * MARGIN - NODE_WIDTH) / (sankey.columns.len() - 1).max(1) as f32;
+    let mut rects = HashMap::new();
+    for (i, column) in sankey.columns.iter().enumerate() {
+        let heights = Vec::from_iter(column.iter().map(|x| MIN_NODE_HEIGHT + (flow(x) * scale) as f32));
+        let total = heights.iter().sum::<f32>() + GAP * (column.len() - 1) as f32;
+        let (x, mut y) = (rect.left() + MARGIN + spacing * i as f32, rect.center().y - total / 2.);
+        for (&node, height) in column.iter().zip(heights) {
+            rects.insert(node, Rect::from_min_size(pos2(x, y), vec2(NODE_WIDTH, height)));
+            y += height + GAP;
+        }
+    }
+
+    // Stack the bands at each node in the order of the nodes at their other ends to avoid crossings.
+    let mut bands = Vec::from_iter(sankey.links.iter().map(|x| (x, 0., 0.)));
+    let mut stacked = HashMap::<NodeId, f32>::new();
+    bands.sort_by(|a, b| rects[&a.0.to].center().y.total_cmp(&rects[&b.0.to].center().y));
+    for (link, offset, _) in &mut bands {
+        let stack = stacked.entry(link.from).or_default();
+        *offset = *stack;
+        *stack += (link.rate.abs() * scale) as f32;
+    }
+    stacked.clear();
+    bands.sort_by(|a, b| rects[&a.0.from].center().y.total_cmp(&rects[&b.0.from].center().y));
+    for (link, _, offset) in &mut bands {
+        let stack = stacked.entry(link.to).or_default();
+        *offset = *stack;
+        *stack += (link.rate.abs() * scale) as f32;
+    }
+
+    for (link, out_offset, in_offset) in bands {
+        let width = (link.rate.abs() * scale) as f32;
+        let (from, to) = (rects[&link.from], rects[&link.to]);
+        let from = pos2(from.right(), from.top() + MIN_NODE_HEIGHT / 2. + out_offset + width / 2.);
+        let to = pos2(to.left(), to.top() + MIN_NODE_HEIGHT / 2. + in_offset + width / 2.);
+        let bend = ((to.x - from.x) / 2.).abs().max(GAP * 2.);
+        let points = [from, from + vec2(bend, 0.), to - vec2(bend, 0.), to];
+        let resource = if let NodeMeta::Resource(_) = &chart[link.from] { link.from } else { link.to };
+        let color = resource_fill(&stats.resource(resource)).unwrap_or(Color32::GRAY).gamma_multiply(0.5);
+        painter.add(CubicBezierShape::from_points_stroke(points, false, Color32::TRANSPARENT, Stroke::new(width.max(1.), color)));
+    }
+
+    let last = sankey.columns.len() - 1;
+    for (i, column) in sankey.columns.iter().enumerate() {
+        for node in column {
+            let rect = rects[node];
+            let fill = stats.nodes.get(node).and_then(node_fill).unwrap_or(Color32::GRAY);
+            painter.rect_filled(rect, CornerRadius::ZERO, fill);
+            let text = format!("{}\n{}", label(chart, *node), fmt.format(flow(node)));
+            let (pos, align) = if i == last && i > 0 {
+                (rect.left_center() - vec2(4., 0.), Align2::RIGHT_CENTER)
+            } else {
+                (rect.right_center() + vec2(4., 0.), Align2::LEFT_CENTER)
+            };
+            painter.text(pos, align, text, FontId::proportional(12.), ui.visuals().text_color());
+        }
+    }
+}
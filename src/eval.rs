@@ -0,0 +1,203 @@
+use crate::model::Variable;
+use meval::tokenizer::Token;
+use meval::{Context, ContextProvider, Expr, FuncEvalError};
+use std::collections::HashMap;
+
+const REF_PREFIX: &str = "__ref";
+
+/// A reference to a computed value of another node, written like `net("Iron Plate")`.
+pub struct Ref {
+    pub func: RefFunc,
+    pub label: String,
+}
+
+#[derive(Clone, Copy)]
+pub enum RefFunc {
+    Net,
+    Inc,
+    Dec,
+    Activity,
+    Capacity,
+    Rate,
+}
+
+impl RefFunc {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "net" => Self::Net,
+            "inc" => Self::Inc,
+            "dec" => Self::Dec,
+            "activity" => Self::Activity,
+            "capacity" => Self::Capacity,
+            "rate" => Self::Rate,
+            _ => return None,
+        })
+    }
+
+    pub fn is_resource(self) -> bool { matches!(self, Self::Net | Self::Inc | Self::Dec) }
+}
+
+/// References in an expression, whether or not it evaluates.
+pub fn refs(expr: &str) -> Vec<Ref> { extract_refs(expr).1 }
+
+/// Replace each reference with a placeholder variable that meval can parse.
+fn extract_refs(expr: &str) -> (String, Vec<Ref>) {
+    let mut result = String::new();
+    let mut refs = Vec::new();
+    let mut rest = expr;
+    while let Some(start) = rest.find(|x: char| x.is_ascii_alphabetic() || x == '_') {
+        result += &rest[..start];
+        rest = &rest[start..];
+        let end = rest.find(|x: char| !x.is_ascii_alphanumeric() && x != '_').unwrap_or(rest.len());
+        let (name, after) = rest.split_at(end);
+        if let Some(func) = RefFunc::parse(name)
+            && let Some(after) = after.trim_start().strip_prefix('(')
+            && let Some(after) = after.trim_start().strip_prefix('"')
+            && let Some((label, after)) = after.split_once('"')
+            && let Some(after) = after.trim_start().strip_prefix(')')
+        {
+            result += &format!("{REF_PREFIX}{}", refs.len());
+            refs.push(Ref { func, label: label.to_owned() });
+            rest = after;
+        } else {
+            result += name;
+            rest = after;
+        }
+    }
+    result += rest;
+    (result, refs)
+}
+
+struct RefContext<'a, F> {
+    ctx: &'a Context<'static>,
+    refs: &'a [Ref],
+    resolve: F,
+}
+
+impl<F: Fn(&Ref) -> Option<f64>> ContextProvider for RefContext<'_, F> {
+    fn get_var(&self, name: &str) -> Option<f64> {
+        match name.strip_prefix(REF_PREFIX).and_then(|x| x.parse::<usize>().ok()) {
+            Some(i) => (self.resolve)(self.refs.get(i)?),
+            None => self.ctx.get_var(name),
+        }
+    }
+
+    fn eval_func(&self, name: &str, args: &[f64]) -> Result<f64, FuncEvalError> { self.ctx.eval_func(name, args) }
+}
+
+/// Evaluation environment with the chart-level variables bound.
+pub struct Env {
+    ctx: Context<'static>,
+    /// Values or error messages of the variables, by name.
+    pub variables: HashMap<String, Result<f64, String>>,
+}
+
+enum VarState<'a> {
+    Pending(&'a str),
+    Visiting,
+    Done,
+}
+
+impl Env {
+    pub fn new(variables: &[Variable]) -> Self {
+        let mut this = Self { ctx: Context::new(), variables: HashMap::new() };
+        let mut states = HashMap::new();
+        for var in variables {
+            let state = if states.contains_key(var.name.as_str()) {
+                this.variables.insert(var.name.clone(), Err("Duplicate variable".to_owned()));
+                VarState::Done
+            } else if !is_identifier(&var.name) {
+                this.variables.insert(var.name.clone(), Err("Invalid name".to_owned()));
+                VarState::Done
+            } else {
+                VarState::Pending(&var.expr)
+            };
+            states.insert(var.name.as_str(), state);
+        }
+        for var in variables {
+            this.resolve(&var.name, &mut states);
+        }
+        this
+    }
+
+    fn resolve<'a>(&mut self, name: &'a str, states: &mut HashMap<&'a str, VarState<'a>>) {
+        let Some(&VarState::Pending(expr)) = states.get(name) else { return };
+        states.insert(name, VarState::Visiting);
+        let result = (|| {
+            let expr = expr.parse::<Expr>().map_err(|e| e.to_string())?;
+            for token in expr.iter() {
+                let Token::Var(dep) = token else { continue };
+                let Some((&dep, state)) = states.get_key_value(dep.as_str()) else { continue };
+                match state {
+                    VarState::Pending(_) => self.resolve(dep, states),
+                    VarState::Visiting => return Err(format!("Cyclic reference to {dep}")),
+                    VarState::Done => (),
+                }
+                if let Some(Err(_)) = self.variables.get(dep) {
+                    return Err(format!("Invalid variable {dep}"));
+                }
+            }
+            expr.eval_with_context(&self.ctx).map_err(|e| e.to_string())
+        })();
+        if let Ok(value) = result {
+            self.ctx.var(name, value);
+        }
+        self.variables.insert(name.to_owned(), result);
+        states.insert(name, VarState::Done);
+    }
+
+    /// Evaluate an expression that may contain references to other nodes, resolved by `resolve`.
+    pub fn eval_with_refs(&self, expr: &str, resolve: impl Fn(&Ref) -> Option<f64>) -> Result<f64, meval::Error> {
+        let (expr, refs) = extract_refs(expr);
+        meval::eval_str_with_context(expr, RefContext { ctx: &self.ctx, refs: &refs, resolve })
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|x| x.is_ascii_alphabetic() || x == '_') && chars.all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(variables: &[(&str, &str)]) -> Env {
+        Env::new(&Vec::from_iter(variables.iter().map(|&(name, expr)| Variable { name: name.to_owned(), expr: expr.to_owned() })))
+    }
+
+    #[test]
+    fn out_of_order() {
+        let env = env(&[("y", "x + 1"), ("x", "1"), ("z", "x * y")]);
+        assert_eq!(env.variables["y"], Ok(2.));
+        assert_eq!(env.variables["z"], Ok(2.));
+        assert_eq!(env.eval_with_refs("z + x", |_| None), Ok(3.));
+    }
+
+    #[test]
+    fn cycles() {
+        let env = env(&[("a", "b"), ("b", "a"), ("c", "c + 1"), ("d", "2")]);
+        assert_eq!(env.variables["b"], Err("Cyclic reference to a".to_owned()));
+        assert_eq!(env.variables["a"], Err("Invalid variable b".to_owned()));
+        assert_eq!(env.variables["c"], Err("Cyclic reference to c".to_owned()));
+        assert_eq!(env.variables["d"], Ok(2.));
+    }
+
+    #[test]
+    fn duplicate_and_invalid_names() {
+        let env = env(&[("c", "1"), ("c", "2"), ("d", "c * 2"), ("1e", "3"), ("f", "1e")]);
+        assert_eq!(env.variables["c"], Err("Duplicate variable".to_owned()));
+        assert_eq!(env.variables["d"], Err("Invalid variable c".to_owned()));
+        assert_eq!(env.variables["1e"], Err("Invalid name".to_owned()));
+        assert!(env.variables["f"].is_err());
+    }
+
+    #[test]
+    fn refs() {
+        let env = env(&[("k", "2")]);
+        let resolve = |x: &Ref| (x.func.is_resource() && x.label == "Iron Plate").then_some(3.);
+        assert_eq!(env.eval_with_refs(r#"k * net( "Iron Plate" )"#, resolve), Ok(6.));
+        assert!(env.eval_with_refs(r#"activity("Iron Plate")"#, resolve).is_err());
+        assert_eq!(super::refs(r#"inc("A") + rate("B") + k"#).len(), 2);
+    }
+}
@@ -0,0 +1,47 @@
+//! Chart building blocks shared by the tests.
+
+use crate::model::{NodeMeta, ProcessMeta, ResourceMeta};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use emath::Pos2;
+
+pub fn resource(chart: &mut Snarl<NodeMeta>, label: &str) -> NodeId {
+    let meta = ResourceMeta { label: label.to_owned(), base_rate: String::new(), use_base_rate: false, nonnegative: false, power: false };
+    chart.insert_node(Pos2::ZERO, NodeMeta::Resource(meta))
+}
+
+pub fn process(chart: &mut Snarl<NodeMeta>, label: &str, capacity: &str, speed: &str, inputs: &[&str], outputs: &[&str]) -> NodeId {
+    let meta = ProcessMeta {
+        label: label.to_owned(),
+        capacity: capacity.to_owned(),
+        speed: speed.to_owned(),
+        inputs: Vec::from_iter(inputs.iter().map(|x| x.to_string())),
+        outputs: Vec::from_iter(outputs.iter().map(|x| x.to_string())),
+        ..<_>::default()
+    };
+    chart.insert_node(Pos2::ZERO, NodeMeta::Process(Box::new(meta)))
+}
+
+pub fn feed(chart: &mut Snarl<NodeMeta>, r: NodeId, node: NodeId, input: usize) {
+    _ = chart.connect(OutPinId { node: r, output: 0 }, InPinId { node, input })
+}
+pub fn emit(chart: &mut Snarl<NodeMeta>, node: NodeId, output: usize, r: NodeId) {
+    _ = chart.connect(OutPinId { node, output }, InPinId { node: r, input: 0 })
+}
+
+pub fn process_meta(chart: &mut Snarl<NodeMeta>, node: NodeId) -> &mut ProcessMeta {
+    let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
+    meta
+}
+
+/// Ore -> Smelter (2 machines) -> Plate -> Assembler (1 machine, 2 plates per gear) -> Gear.
+pub fn gear_chain() -> (Snarl<NodeMeta>, [NodeId; 5]) {
+    let mut chart = Snarl::new();
+    let (ore, plate, gear) = (resource(&mut chart, "Ore"), resource(&mut chart, "Plate"), resource(&mut chart, "Gear"));
+    let smelter = process(&mut chart, "Smelter", "2", "1", &["1"], &["1"]);
+    let assembler = process(&mut chart, "Assembler", "1", "1", &["2"], &["1"]);
+    feed(&mut chart, ore, smelter, 0);
+    emit(&mut chart, smelter, 0, plate);
+    feed(&mut chart, plate, assembler, 0);
+    emit(&mut chart, assembler, 0, gear);
+    (chart, [ore, plate, gear, smelter, assembler])
+}
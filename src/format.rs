@@ -1,8 +1,10 @@
+use crate::THRESHOLD;
+use serde::{Deserialize, Serialize};
 use std::slice;
 
 fn format_decimal(mut value: u64, mut scale: i32, neg: bool) -> String {
     let true = value > 0 else { return "0".to_owned() };
-    while value % 10 == 0 {
+    while value.is_multiple_of(10) {
         value /= 10;
         scale += 1;
     }
@@ -33,7 +35,7 @@ fn format_decimal(mut value: u64, mut scale: i32, neg: bool) -> String {
     let neg = if neg { "-" } else { "" };
     let true = frac > 0 else { return format!("{neg}{int} {prefix}") };
     let mut places = 3;
-    while frac % 10 == 0 {
+    while frac.is_multiple_of(10) {
         frac /= 10;
         places -= 1;
     }
@@ -64,3 +66,129 @@ pub fn format_float(float: f64, threshold: f64) -> String {
         }
     }
 }
+
+/// Plain decimal for writing a computed value into an expression field: zero below `threshold` like [`format_float`],
+/// otherwise rounded to 12 significant digits to drop noise like `0.30000000000000004`.
+pub fn format_expr(float: f64, threshold: f64) -> String {
+    let true = float.abs() >= threshold else { return "0".to_owned() };
+    format!("{float:.11e}").parse::<f64>().unwrap().to_string()
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum TimeUnit {
+    Second,
+    Minute,
+    Hour,
+}
+
+impl TimeUnit {
+    pub const ALL: [Self; 3] = [Self::Second, Self::Minute, Self::Hour];
+
+    pub fn seconds(self) -> f64 {
+        match self {
+            Self::Second => 1.,
+            Self::Minute => 60.,
+            Self::Hour => 3600.,
+        }
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::Second => "/s",
+            Self::Minute => "/min",
+            Self::Hour => "/h",
+        }
+    }
+}
+
+/// Formats rates given per the chart's time unit in the display time unit.
+#[derive(Clone, Copy)]
+pub struct RateFormat {
+    scale: f64,
+    suffix: &'static str,
+}
+
+impl RateFormat {
+    /// If the chart has no time unit, rates are shown as-is without a suffix.
+    pub fn new(chart: Option<TimeUnit>, display: TimeUnit) -> Self {
+        let Some(chart) = chart else { return Self { scale: 1., suffix: "" } };
+        Self { scale: display.seconds() / chart.seconds(), suffix: display.suffix() }
+    }
+
+    pub fn format(&self, rate: f64) -> String {
+        let mut result = format_float(rate * self.scale, THRESHOLD * self.scale);
+        if !self.suffix.is_empty() {
+            (!result.ends_with(' ')).then(|| result.push(' '));
+            result += self.suffix;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approx_decimal_keeps_four_digits() {
+        assert_eq!(approx_decimal(0.), (0, 0));
+        assert_eq!(approx_decimal(1.), (1000, -3));
+        assert_eq!(approx_decimal(1234.5), (1235, 0));
+        assert_eq!(approx_decimal(999.9995), (10000, -1));
+        assert_eq!(approx_decimal(2E13), (20000000, 6));
+    }
+
+    #[test]
+    fn format_decimal_picks_prefix() {
+        assert_eq!(format_decimal(0, 5, false), "0");
+        assert_eq!(format_decimal(1000, -3, false), "1 ");
+        assert_eq!(format_decimal(10000, -1, false), "1 k");
+        assert_eq!(format_decimal(12345, 0, true), "-12.345 k");
+        assert_eq!(format_decimal(25, -7, false), "2.5 μ");
+        assert_eq!(format_decimal(1, 33, false), "≈∞");
+        assert_eq!(format_decimal(1, 33, true), "≈-∞");
+        assert_eq!(format_decimal(1, -31, false), "≈0");
+    }
+
+    #[test]
+    fn format_float_edge_cases() {
+        assert_eq!(format_float(0., THRESHOLD), "0");
+        assert_eq!(format_float(1E-10, THRESHOLD), "≈0");
+        assert_eq!(format_float(-1E-10, THRESHOLD), "≈0");
+        assert_eq!(format_float(1E-10, 0.), "100 p");
+        assert_eq!(format_float(1.5, THRESHOLD), "1.5 ");
+        assert_eq!(format_float(-1., THRESHOLD), "-1 ");
+        assert_eq!(format_float(0.5, THRESHOLD), "500 m");
+        assert_eq!(format_float(2. / 3., THRESHOLD), "666.7 m");
+        assert_eq!(format_float(0.1 + 0.2, THRESHOLD), "300 m");
+        assert_eq!(format_float(999., THRESHOLD), "999 ");
+        assert_eq!(format_float(999.9995, THRESHOLD), "1 k");
+        assert_eq!(format_float(-999.9995, THRESHOLD), "-1 k");
+        assert_eq!(format_float(1E6, THRESHOLD), "1 M");
+        assert_eq!(format_float(1.5E12, THRESHOLD), "1.5 T");
+        assert_eq!(format_float(1E32, THRESHOLD), "100 Q");
+        assert_eq!(format_float(1E33, THRESHOLD), "≈∞");
+        assert_eq!(format_float(-1E40, THRESHOLD), "≈-∞");
+        assert_eq!(format_float(f64::INFINITY, THRESHOLD), "≈∞");
+        assert_eq!(format_float(1E-30, 0.), "1 q");
+        assert_eq!(format_float(1E-31, 0.), "≈0");
+    }
+
+    #[test]
+    fn format_expr_rounds_noise() {
+        assert_eq!(format_expr(0.1 + 0.2, THRESHOLD), "0.3");
+        assert_eq!(format_expr(-2. / 3., THRESHOLD), "-0.666666666667");
+        assert_eq!(format_expr(1E-10, THRESHOLD), "0");
+        assert_eq!(format_expr(1.5E20, THRESHOLD), "150000000000000000000");
+        assert_eq!(format_expr(6., THRESHOLD), "6");
+    }
+
+    #[test]
+    fn rate_format_converts_units() {
+        let fmt = RateFormat::new(Some(TimeUnit::Second), TimeUnit::Minute);
+        assert_eq!(fmt.format(7.5), "450 /min");
+        assert_eq!(fmt.format(0.5), "30 /min");
+        assert_eq!(RateFormat::new(Some(TimeUnit::Hour), TimeUnit::Second).format(36.), "10 m /s");
+        assert_eq!(RateFormat::new(None, TimeUnit::Minute).format(2.), "2 ");
+    }
+}
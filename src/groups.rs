@@ -0,0 +1,94 @@
+use eframe::egui::{Area, Color32, Frame, Grid, Label, Order, Rect, RichText, Sense, Stroke, TextEdit, Ui, vec2};
+use eframe::emath::TSTransform;
+use egui_snarl::{NodeId, Snarl};
+use factory_balancer::format::RateFormat;
+use factory_balancer::model::{Group, NodeMeta};
+use factory_balancer::stats::ChartStats;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+const MARGIN: f32 = 12.;
+/// Room above the nodes for the header.
+const HEADER: f32 = 24.;
+const COLORS: [[u8; 3]; 6] = [[70, 130, 180], [60, 160, 90], [200, 140, 40], [170, 80, 170], [190, 70, 70], [80, 160, 160]];
+
+pub fn color(group: &Group) -> Color32 { Color32::from_rgb(group.color[0], group.color[1], group.color[2]) }
+
+/// Frame around the nodes of a group in graph space, from their rects relative to their positions when last drawn.
+pub fn frame(chart: &Snarl<NodeMeta>, group: &Group, node_rects: &HashMap<NodeId, Rect>) -> Option<Rect> {
+    let rects = group.nodes.iter().filter_map(|x| Some(node_rects.get(x)?.translate(chart.get_node_info(*x)?.pos.to_vec2())));
+    let rect = rects.reduce(Rect::union)?.expand(MARGIN);
+    Some(rect.with_min_y(rect.min.y - HEADER))
+}
+
+/// New group of `nodes`, taking them out of the groups they were in.
+pub fn new_group(groups: &mut Vec<Group>, nodes: Vec<NodeId>) {
+    groups.iter_mut().for_each(|x| x.nodes.retain(|x| !nodes.contains(x)));
+    let color = COLORS[groups.len() % COLORS.len()];
+    groups.push(Group { label: String::new(), color, nodes: BTreeSet::from_iter(nodes), collapsed: false });
+}
+
+/// Drop removed nodes from the groups, and the groups left empty. Only the nodes of collapsed groups are shown collapsed.
+pub fn sync_groups(chart: &mut Snarl<NodeMeta>, groups: &mut Vec<Group>) {
+    groups.iter_mut().for_each(|x| x.nodes.retain(|&x| chart.get_node(x).is_some()));
+    groups.retain(|x| !x.nodes.is_empty());
+    let collapsed = HashSet::<NodeId>::from_iter(groups.iter().filter(|x| x.collapsed).flat_map(|x| x.nodes.iter().copied()));
+    let nodes = Vec::from_iter(chart.node_ids().map(|x| x.0));
+    nodes.into_iter().for_each(|x| chart.open_node(x, !collapsed.contains(&x)));
+}
+
+/// Over the chart, the header of each group for dragging its nodes around, collapsing and editing it, and under the header of
+/// each collapsed group, the net rates at which its processes draw and supply resources.
+pub fn show_groups(
+    ui: &Ui, chart: &mut Snarl<NodeMeta>, groups: &mut Vec<Group>, stats: &ChartStats, fmt: &RateFormat, node_rects: &HashMap<NodeId, Rect>,
+    to_global: TSTransform,
+) {
+    let (clip, mut remove) = (ui.clip_rect(), None);
+    for (i, group) in groups.iter_mut().enumerate() {
+        let Some(rect) = frame(chart, group, node_rects) else { continue };
+        let rect = to_global * rect;
+        let color = color(group);
+        let area = Area::new(ui.id().with(("group", i))).fixed_pos(rect.min).order(Order::Middle).constrain(false);
+        area.show(ui.ctx(), |ui| {
+            ui.set_clip_rect(clip);
+            let mut frame = Frame::new().inner_margin(4.);
+            if group.collapsed {
+                frame = frame.fill(ui.visuals().panel_fill).stroke(Stroke::new(1.5, color)).corner_radius(6.);
+            }
+            frame.show(ui, |ui| {
+                group.collapsed.then(|| ui.set_min_size(rect.size() - vec2(8., 8.)));
+                ui.horizontal(|ui| {
+                    ui.small_button(if group.collapsed { "⏵" } else { "⏷" }).clicked().then(|| group.collapsed = !group.collapsed);
+                    let text = if group.label.is_empty() { "(unnamed)" } else { &group.label };
+                    let resp = ui.add(Label::new(RichText::new(text).strong().color(color)).sense(Sense::drag()));
+                    if resp.dragged() {
+                        let delta = resp.drag_delta() / to_global.scaling;
+                        group.nodes.iter().for_each(|&x| chart.get_node_info_mut(x).unwrap().pos += delta);
+                    }
+                    resp.context_menu(|ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Label");
+                            TextEdit::singleline(&mut group.label).desired_width(120.).show(ui);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Color");
+                            ui.color_edit_button_srgb(&mut group.color);
+                        });
+                        ui.button("Ungroup").clicked().then(|| remove = Some(i));
+                    });
+                });
+                let true = group.collapsed else { return };
+                let flows = stats.group_flows(chart, &group.nodes);
+                let false = flows.is_empty() else { return drop(ui.weak("No net flows")) };
+                Grid::new("flows").show(ui, |ui| {
+                    for (node, rate) in flows {
+                        let NodeMeta::Resource(meta) = &chart[node] else { unreachable!() };
+                        ui.label(&meta.label);
+                        ui.label(rate.map_or("invalid".to_owned(), |x| fmt.format(x)));
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    }
+    remove.map(|x| groups.remove(x));
+}
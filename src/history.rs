@@ -0,0 +1,54 @@
+use factory_balancer::model::Document;
+use std::mem::{replace, take};
+
+const LIMIT: usize = 256;
+
+/// Snapshots of the document for undo and redo.
+pub struct History {
+    pub undo: Vec<Document>,
+    pub redo: Vec<Document>,
+    /// The latest snapshot and its serialization, to detect changes.
+    last: (Document, String),
+    /// Whether the document may have changed since the latest snapshot.
+    pub dirty: bool,
+}
+
+impl History {
+    pub fn new(doc: &Document) -> Self {
+        Self { undo: Vec::new(), redo: Vec::new(), last: (doc.clone(), ron::to_string(doc).unwrap_or_default()), dirty: false }
+    }
+
+    /// Take a snapshot if the document is dirty and changed since the last one.
+    pub fn commit(&mut self, doc: &Document) {
+        let true = take(&mut self.dirty) else { return };
+        let Ok(data) = ron::to_string(doc) else { return };
+        let false = data == self.last.1 else { return };
+        self.undo.push(replace(&mut self.last, (doc.clone(), data)).0);
+        (self.undo.len() > LIMIT).then(|| self.undo.remove(0));
+        self.redo.clear();
+    }
+
+    /// Move to the snapshot `doc`, returning the one it replaces.
+    fn restore(&mut self, doc: &mut Document, snapshot: Document) -> Document {
+        self.last.1 = ron::to_string(&snapshot).unwrap_or_default();
+        *doc = snapshot.clone();
+        replace(&mut self.last.0, snapshot)
+    }
+
+    /// Undo, keeping any edit not yet committed for redo.
+    pub fn undo(&mut self, doc: &mut Document) {
+        self.dirty = true;
+        self.commit(doc);
+        let Some(snapshot) = self.undo.pop() else { return };
+        let redo = self.restore(doc, snapshot);
+        self.redo.push(redo);
+    }
+
+    pub fn redo(&mut self, doc: &mut Document) {
+        self.dirty = true;
+        self.commit(doc);
+        let Some(snapshot) = self.redo.pop() else { return };
+        let undo = self.restore(doc, snapshot);
+        self.undo.push(undo);
+    }
+}
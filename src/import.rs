@@ -0,0 +1,278 @@
+use crate::library::Recipe;
+use crate::model::{Chance, ProcessMeta};
+use anyhow::{Context as _, Result, bail, ensure};
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap};
+
+/// Machine types of Factorio that craft recipes.
+const FACTORIO_MACHINES: [&str; 3] = ["assembling-machine", "furnace", "rocket-silo"];
+
+fn recipe(label: String, duration: f64, crafting_speed: f64, inputs: Vec<(String, f64)>, outputs: Vec<(String, f64, Chance)>) -> Recipe {
+    // Certain outputs of an input are given back on its pin, leaving a single wire for a catalyst.
+    let mut input_returns = vec![String::new(); inputs.len()];
+    let outputs = outputs.into_iter().filter(|(label, qty, chance)| {
+        let (Some(i), true) = (inputs.iter().position(|x| x.0 == *label), *chance == Chance::default()) else { return true };
+        (input_returns[i] = qty.to_string(), false).1
+    });
+    let outputs = Vec::from_iter(outputs);
+    input_returns.iter().all(String::is_empty).then(|| input_returns.clear());
+    let (inputs, input_qty): (Vec<_>, Vec<_>) = inputs.into_iter().map(|(label, qty)| (label, qty.to_string())).unzip();
+    let (outputs, (output_qty, mut output_chances)): (Vec<_>, (Vec<_>, Vec<_>)) =
+        outputs.into_iter().map(|(label, qty, chance)| (label, (qty.to_string(), chance))).unzip();
+    output_chances.iter().all(|x| *x == Chance::default()).then(|| output_chances.clear());
+    let process = ProcessMeta {
+        label,
+        capacity: "1".to_owned(),
+        duration: duration.to_string(),
+        crafting_speed: crafting_speed.to_string(),
+        use_duration: true,
+        inputs: input_qty,
+        outputs: output_qty,
+        input_returns,
+        output_chances,
+        ..<_>::default()
+    };
+    Recipe { process, inputs, outputs }
+}
+
+pub struct Import {
+    pub recipes: Vec<Recipe>,
+    /// Where more than one machine can make a Factorio crafting category or a Satisfactory recipe, the fastest one that
+    /// was picked, and the others with their speeds.
+    pub choices: BTreeSet<String>,
+}
+
+/// Convert a game data dump into recipes, detecting the format by its shape:
+/// Factorio's `data-raw` dump is an object of prototype types, Satisfactory's `Docs.json` an array of native classes.
+/// Durations are in seconds. Recipes that no machine can make are skipped.
+pub fn import_recipes(text: &str) -> Result<Import> {
+    let mut choices = BTreeSet::new();
+    let recipes = match serde_json::from_str(text.trim_start_matches('\u{feff}'))? {
+        Value::Object(x) if x.contains_key("recipe") => import_factorio(&x, &mut choices)?,
+        Value::Array(x) => import_satisfactory(&x, &mut choices)?,
+        _ => bail!("Unrecognized data format"),
+    };
+    ensure!(!recipes.is_empty(), "No recipes found");
+    Ok(Import { recipes, choices })
+}
+
+/// Crafting speed of the fastest of `machines`, noting the choice under `what` if there are others.
+fn fastest(what: &str, machines: &[(&str, f64)], choices: &mut BTreeSet<String>) -> Option<f64> {
+    let &(name, speed) = machines.iter().max_by(|x, y| x.1.total_cmp(&y.1))?;
+    if machines.len() > 1 {
+        let others = Vec::from_iter(machines.iter().filter(|x| x.0 != name).map(|(name, speed)| format!("{name} ({speed})")));
+        choices.insert(format!("{what}: {name} ({speed}) over {}", others.join(", ")));
+    }
+    Some(speed)
+}
+
+/// Parse an ingredient or result, either `{"name": ..., "amount": ...}` or `[name, amount]`.
+/// Random or probabilistic results keep their chance, with the minimum amount as the quantity.
+fn factorio_item(x: &Value) -> Option<(String, f64, Chance)> {
+    if let Some([name, amount]) = x.as_array().map(Vec::as_slice) {
+        return Some((name.as_str()?.to_owned(), amount.as_f64()?, Chance::default()));
+    }
+    let name = x["name"].as_str()?.to_owned();
+    let (min, max) = match x["amount"].as_f64() {
+        Some(x) => (x, x),
+        None => (x["amount_min"].as_f64()?, x["amount_max"].as_f64()?),
+    };
+    let extra = x["extra_count_fraction"].as_f64().unwrap_or(0.);
+    let chance = Chance {
+        probability: x["probability"].as_f64().filter(|&x| x != 1.).map_or(String::new(), |x| x.to_string()),
+        max: if max == min { String::new() } else { (max + extra).to_string() },
+    };
+    Some((name, min + extra, chance))
+}
+
+fn import_factorio(data: &Map<String, Value>, choices: &mut BTreeSet<String>) -> Result<Vec<Recipe>> {
+    let mut machines = HashMap::<&str, Vec<(&str, f64)>>::new();
+    for (name, machine) in FACTORIO_MACHINES.iter().filter_map(|x| data.get(*x)?.as_object()).flatten() {
+        let Some(speed) = machine["crafting_speed"].as_f64() else { continue };
+        for category in machine["crafting_categories"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            machines.entry(category).or_default().push((name, speed));
+        }
+    }
+    let mut result = Vec::new();
+    for (name, x) in data["recipe"].as_object().context("Invalid recipe list")? {
+        let category = x["category"].as_str().unwrap_or("crafting");
+        let Some(speed) = fastest(category, machines.get(category).map_or(&[], Vec::as_slice), choices) else { continue };
+        let x = x.get("normal").filter(|x| x.is_object()).unwrap_or(x);
+        let items = |x: &Value| Vec::from_iter(x.as_array().into_iter().flatten().filter_map(factorio_item));
+        let mut outputs = items(&x["results"]);
+        if let Some(item) = x["result"].as_str() {
+            outputs.push((item.to_owned(), x["result_count"].as_f64().unwrap_or(1.), Chance::default()));
+        }
+        let inputs = Vec::from_iter(items(&x["ingredients"]).into_iter().map(|x| (x.0, x.1)));
+        let duration = x["energy_required"].as_f64().unwrap_or(0.5);
+        result.push(recipe(name.clone(), duration, speed, inputs, outputs));
+    }
+    Ok(result)
+}
+
+/// Extract the class name from an object path like `"/Game/.../Desc_IronIngot.Desc_IronIngot_C"`.
+fn class_name(path: &str) -> &str {
+    let path = path.trim_end_matches(['"', '\'']);
+    path.rsplit_once('.').map_or(path, |x| x.1)
+}
+
+/// Parse a list of items like `((ItemClass=...,Amount=3),(ItemClass=...,Amount=2))`.
+fn satisfactory_items(text: &str) -> impl Iterator<Item = (&str, f64)> {
+    text.split("ItemClass=").skip(1).filter_map(|x| {
+        let (class, rest) = x.split_once(",Amount=")?;
+        let amount = rest.split(')').next()?.parse().ok()?;
+        Some((class_name(class), amount))
+    })
+}
+
+fn import_satisfactory(data: &[Value], choices: &mut BTreeSet<String>) -> Result<Vec<Recipe>> {
+    let classes = |native: &'static str| {
+        let groups = data.iter().filter(move |x| x["NativeClass"].as_str().is_some_and(|x| x.contains(native)));
+        groups.flat_map(|x| x["Classes"].as_array().into_iter().flatten())
+    };
+    let mut items = HashMap::new();
+    let mut speeds = HashMap::new();
+    for x in data.iter().flat_map(|x| x["Classes"].as_array().into_iter().flatten()) {
+        let name = x["ClassName"].as_str().unwrap_or_default();
+        if let Some(form) = x["mForm"].as_str() {
+            let scale = if matches!(form, "RF_LIQUID" | "RF_GAS") { 1E-3 } else { 1. };
+            items.insert(name, (x["mDisplayName"].as_str().unwrap_or(name), scale));
+        }
+    }
+    for x in classes("FGBuildableManufacturer") {
+        let Some(speed) = x["mManufacturingSpeed"].as_str().and_then(|x| x.parse::<f64>().ok()) else { continue };
+        let name = x["ClassName"].as_str().unwrap_or_default();
+        speeds.insert(name, (x["mDisplayName"].as_str().unwrap_or(name), speed));
+    }
+    let mut result = Vec::new();
+    for x in classes("FGRecipe'") {
+        let produced_in = x["mProducedIn"].as_str().unwrap_or_default();
+        let machines = Vec::from_iter(produced_in.split(',').filter_map(|x| speeds.get(class_name(x.trim_matches(['(', ')'])))).copied());
+        let label = x["mDisplayName"].as_str().context("Recipe without a name")?.to_owned();
+        let Some(speed) = fastest(&label, &machines, choices) else { continue };
+        let convert = |text: &str| {
+            let items = satisfactory_items(text).map(|(class, amount)| match items.get(class) {
+                Some(&(name, scale)) => (name.to_owned(), amount * scale),
+                None => (class.to_owned(), amount),
+            });
+            Vec::from_iter(items)
+        };
+        let outputs = convert(x["mProduct"].as_str().unwrap_or_default()).into_iter().map(|(label, qty)| (label, qty, Chance::default()));
+        let duration = x["mManufactoringDuration"].as_str().and_then(|x| x.parse().ok()).context("Recipe without a duration")?;
+        let inputs = convert(x["mIngredients"].as_str().unwrap_or_default());
+        result.push(recipe(label, duration, speed, inputs, Vec::from_iter(outputs)));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FACTORIO: &str = r#"{
+        "assembling-machine": {
+            "assembling-machine-1": {"crafting_speed": 0.5, "crafting_categories": ["crafting"]},
+            "assembling-machine-2": {"crafting_speed": 0.75, "crafting_categories": ["crafting", "advanced-crafting"]},
+            "oil-refinery": {"crafting_speed": 1, "crafting_categories": ["oil-processing"]}
+        },
+        "furnace": {"stone-furnace": {"crafting_speed": 1, "crafting_categories": ["smelting"]}},
+        "recipe": {
+            "iron-gear-wheel": {"ingredients": [["iron-plate", 2]], "result": "iron-gear-wheel"},
+            "iron-plate": {
+                "category": "smelting",
+                "energy_required": 3.2,
+                "ingredients": [{"type": "item", "name": "iron-ore", "amount": 1}],
+                "results": [{"type": "item", "name": "iron-plate", "amount": 1}, {"name": "slag", "amount_min": 1, "amount_max": 3, "probability": 0.5}]
+            },
+            "coal-liquefaction": {
+                "category": "oil-processing",
+                "energy_required": 5,
+                "ingredients": [{"name": "coal", "amount": 10}, {"name": "heavy-oil", "amount": 25}],
+                "results": [{"name": "heavy-oil", "amount": 90}, {"name": "light-oil", "amount": 20}]
+            },
+            "uranium-processing": {"category": "centrifuging", "ingredients": [["uranium-ore", 10]], "result": "uranium-235"}
+        }
+    }"#;
+
+    const SATISFACTORY: &str = r##"[
+        {
+            "NativeClass": "/Script/CoreUObject.Class'/Script/FactoryGame.FGResourceDescriptor'",
+            "Classes": [
+                {"ClassName": "Desc_OreIron_C", "mDisplayName": "Iron Ore", "mForm": "RF_SOLID"},
+                {"ClassName": "Desc_Water_C", "mDisplayName": "Water", "mForm": "RF_LIQUID"}
+            ]
+        },
+        {
+            "NativeClass": "/Script/CoreUObject.Class'/Script/FactoryGame.FGItemDescriptor'",
+            "Classes": [{"ClassName": "Desc_IronIngot_C", "mDisplayName": "Iron Ingot", "mForm": "RF_SOLID"}]
+        },
+        {
+            "NativeClass": "/Script/CoreUObject.Class'/Script/FactoryGame.FGBuildableManufacturer'",
+            "Classes": [
+                {"ClassName": "Build_SmelterMk1_C", "mDisplayName": "Smelter", "mManufacturingSpeed": "1.000000"},
+                {"ClassName": "Build_FoundryMk1_C", "mDisplayName": "Foundry", "mManufacturingSpeed": "2.000000"}
+            ]
+        },
+        {
+            "NativeClass": "/Script/CoreUObject.Class'/Script/FactoryGame.FGRecipe'",
+            "Classes": [
+                {
+                    "ClassName": "Recipe_IngotIron_C",
+                    "mDisplayName": "Iron Ingot",
+                    "mIngredients": "((ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/RawResources/OreIron/Desc_OreIron.Desc_OreIron_C'\",Amount=1))",
+                    "mProduct": "((ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/Parts/IronIngot/Desc_IronIngot.Desc_IronIngot_C'\",Amount=1))",
+                    "mManufactoringDuration": "2.000000",
+                    "mProducedIn": "(\"/Game/FactoryGame/Buildable/Factory/SmelterMk1/Build_SmelterMk1.Build_SmelterMk1_C\",\"/Game/FactoryGame/Buildable/Factory/FoundryMk1/Build_FoundryMk1.Build_FoundryMk1_C\")"
+                },
+                {
+                    "ClassName": "Recipe_Alternate_PureIronIngot_C",
+                    "mDisplayName": "Alternate: Pure Iron Ingot",
+                    "mIngredients": "((ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/RawResources/OreIron/Desc_OreIron.Desc_OreIron_C'\",Amount=7),(ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/RawResources/Water/Desc_Water.Desc_Water_C'\",Amount=4000))",
+                    "mProduct": "((ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/Parts/IronIngot/Desc_IronIngot.Desc_IronIngot_C'\",Amount=13))",
+                    "mManufactoringDuration": "12.000000",
+                    "mProducedIn": "(\"/Game/FactoryGame/Buildable/Factory/FoundryMk1/Build_FoundryMk1.Build_FoundryMk1_C\")"
+                },
+                {
+                    "ClassName": "Recipe_IronPlate_C",
+                    "mDisplayName": "Iron Plate",
+                    "mIngredients": "((ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/Parts/IronIngot/Desc_IronIngot.Desc_IronIngot_C'\",Amount=3))",
+                    "mProduct": "((ItemClass=\"/Script/Engine.BlueprintGeneratedClass'/Game/FactoryGame/Resource/Parts/IronPlate/Desc_IronPlate.Desc_IronPlate_C'\",Amount=2))",
+                    "mManufactoringDuration": "6.000000",
+                    "mProducedIn": "(\"/Game/FactoryGame/Buildable/-Shared/WorkBench/BP_WorkBenchComponent.BP_WorkBenchComponent_C\")"
+                }
+            ]
+        }
+    ]"##;
+
+    fn find<'a>(import: &'a Import, label: &str) -> &'a Recipe { import.recipes.iter().find(|x| x.process.label == label).unwrap() }
+
+    #[test]
+    fn factorio() {
+        let import = import_recipes(FACTORIO).unwrap();
+        assert_eq!(import.recipes.len(), 3);
+        assert_eq!(Vec::from_iter(&import.choices), ["crafting: assembling-machine-2 (0.75) over assembling-machine-1 (0.5)"]);
+        let gear = &find(&import, "iron-gear-wheel").process;
+        assert_eq!((&*gear.crafting_speed, &*gear.duration), ("0.75", "0.5"));
+        assert_eq!((&gear.inputs, &gear.outputs), (&vec!["2".to_owned()], &vec!["1".to_owned()]));
+        let plate = find(&import, "iron-plate");
+        assert_eq!((&*plate.process.crafting_speed, &*plate.process.duration), ("1", "3.2"));
+        assert_eq!(plate.outputs, ["iron-plate", "slag"]);
+        assert!(plate.process.output_chances[1] == Chance { probability: "0.5".to_owned(), max: "3".to_owned() });
+        let liquefaction = find(&import, "coal-liquefaction");
+        assert_eq!(liquefaction.process.input_returns, ["", "90"]);
+        assert_eq!(liquefaction.outputs, ["light-oil"]);
+    }
+
+    #[test]
+    fn satisfactory() {
+        let import = import_recipes(SATISFACTORY).unwrap();
+        assert_eq!(import.recipes.len(), 2);
+        assert_eq!(Vec::from_iter(&import.choices), ["Iron Ingot: Foundry (2) over Smelter (1)"]);
+        let ingot = find(&import, "Iron Ingot");
+        assert_eq!((&*ingot.process.crafting_speed, &*ingot.process.duration), ("2", "2"));
+        assert_eq!((&ingot.inputs, &ingot.outputs), (&vec!["Iron Ore".to_owned()], &vec!["Iron Ingot".to_owned()]));
+        let pure = find(&import, "Alternate: Pure Iron Ingot");
+        assert_eq!(pure.inputs, ["Iron Ore", "Water"]);
+        assert_eq!((&pure.process.inputs, &pure.process.outputs), (&vec!["7".to_owned(), "4".to_owned()], &vec!["13".to_owned()]));
+    }
+}
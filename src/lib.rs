@@ -0,0 +1,18 @@
+//! Chart model, evaluation and solvers of the factory balancer, independent of the UI.
+//! Build with `default-features = false` to leave out the app and its dependencies.
+
+pub mod balance;
+pub mod eval;
+#[cfg(test)]
+mod fixtures;
+pub mod format;
+pub mod import;
+pub mod library;
+pub mod model;
+pub mod report;
+pub mod sankey;
+pub mod solver;
+pub mod stats;
+
+/// Rates and activities closer to zero than this are treated as zero.
+pub const THRESHOLD: f64 = 1E-9;
@@ -0,0 +1,98 @@
+use crate::model::{NodeMeta, ProcessMeta, ResourceMeta};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use emath::{Pos2, vec2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Library {
+    pub recipes: Vec<Recipe>,
+}
+
+/// A process template. Its name is the label of the process.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Recipe {
+    pub process: ProcessMeta,
+    /// Labels of the resources connected to each input.
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    /// Labels of the resources connected to each output.
+    #[serde(default)]
+    pub outputs: Vec<String>,
+}
+
+/// Drag-and-drop payload for inserting a recipe.
+pub struct RecipeDrag(pub usize);
+
+fn resource_label(chart: &Snarl<NodeMeta>, remotes: impl IntoIterator<Item = NodeId>) -> String {
+    let mut remotes = remotes.into_iter();
+    if let (Some(node), None) = (remotes.next(), remotes.next())
+        && let NodeMeta::Resource(meta) = &chart[node]
+    {
+        meta.label.clone()
+    } else {
+        String::new()
+    }
+}
+
+impl Library {
+    /// Replace any recipe with the same name.
+    pub fn insert(&mut self, recipe: Recipe) {
+        if let Some(x) = self.recipes.iter_mut().find(|x| x.process.label == recipe.process.label) {
+            *x = recipe;
+        } else {
+            self.recipes.push(recipe);
+            self.recipes.sort_by(|a, b| a.process.label.cmp(&b.process.label));
+        }
+    }
+}
+
+impl Recipe {
+    pub fn from_process(chart: &Snarl<NodeMeta>, node: NodeId) -> Self {
+        let NodeMeta::Process(meta) = &chart[node] else { unreachable!() };
+        let mut process = ProcessMeta::clone(meta);
+        process.activity.clear();
+        process.locked = false;
+        process.input_weights.clear();
+        process.output_weights.clear();
+        let inputs = (0..meta.inputs.len()).map(|input| chart.in_pin(InPinId { node, input }).remotes.into_iter().map(|x| x.node));
+        let outputs = (0..meta.outputs.len()).map(|output| chart.out_pin(OutPinId { node, output }).remotes.into_iter().map(|x| x.node));
+        Self {
+            process,
+            inputs: Vec::from_iter(inputs.map(|x| resource_label(chart, x))),
+            outputs: Vec::from_iter(outputs.map(|x| resource_label(chart, x))),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let join = |x: &[String], qty: &[String]| {
+            let items = x.iter().zip(qty).map(|(label, qty)| format!("{qty} {}", if label.is_empty() { "?" } else { label }));
+            Vec::from_iter(items).join(" + ")
+        };
+        format!("{} → {}", join(&self.inputs, &self.process.inputs), join(&self.outputs, &self.process.outputs))
+    }
+
+    /// If `connect` is set, wire each labelled input and output to the resource with the same label,
+    /// creating it if there's none.
+    pub fn instantiate(&self, chart: &mut Snarl<NodeMeta>, pos: Pos2, connect: bool) -> NodeId {
+        let node = chart.insert_node(pos, NodeMeta::Process(Box::new(self.process.clone())));
+        let true = connect else { return node };
+        let mut find_or_insert = |label: &String, pos: Pos2| {
+            let found = chart.node_ids().find(|(_, x)| matches!(x, NodeMeta::Resource(x) if x.label == *label));
+            found.map(|x| x.0).unwrap_or_else(|| {
+                let meta = ResourceMeta { label: label.clone(), base_rate: String::new(), use_base_rate: false, nonnegative: false, power: false };
+                chart.insert_node(pos, NodeMeta::Resource(meta))
+            })
+        };
+        let mut wires = Vec::new();
+        for (input, label) in self.inputs.iter().enumerate().filter(|x| !x.1.is_empty()) {
+            let r = find_or_insert(label, pos + vec2(-160., 80. * input as f32));
+            wires.push((OutPinId { node: r, output: 0 }, InPinId { node, input }));
+        }
+        for (output, label) in self.outputs.iter().enumerate().filter(|x| !x.1.is_empty()) {
+            let r = find_or_insert(label, pos + vec2(320., 80. * output as f32));
+            wires.push((OutPinId { node, output }, InPinId { node: r, input: 0 }));
+        }
+        wires.into_iter().for_each(|(from, to)| _ = chart.connect(from, to));
+        node
+    }
+}
@@ -1,446 +1,107 @@
-mod format;
+mod diagram;
+mod groups;
+mod history;
+mod platform;
+mod storage;
+mod summary;
+mod viewer;
+
+use crate::diagram::show_sankey;
+use crate::groups::{new_group, show_groups, sync_groups};
+use crate::history::History;
+use crate::platform::{pick_file, read_clipboard, spawn};
+use crate::storage::Storage;
+use crate::summary::Summary;
+use crate::viewer::{Action, ChartViewer, PinPositions, WIRE_FRAME_SIZE};
+use anyhow::{Result, ensure};
+use eframe::CreationContext;
+use eframe::egui::{Button, CentralPanel, Color32, Context, Event, Key, Modal, Rect, TextWrapMode, Vec2, vec2};
+use eframe::egui::{ComboBox, DragAndDrop, KeyboardShortcut, Modifiers, OpenUrl, SidePanel, TextEdit, ThemePreference, TopBottomPanel};
+use eframe::emath::TSTransform;
+use egui_snarl::ui::{PinPlacement, SnarlStyle, get_selected_nodes};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use factory_balancer::THRESHOLD;
+use factory_balancer::balance::{Objective, balance_all, optimize, round_up_capacities, tight_integer};
+use factory_balancer::eval::Env;
+use factory_balancer::format::{RateFormat, TimeUnit, format_float};
+use factory_balancer::import::import_recipes;
+use factory_balancer::library::{Library, Recipe, RecipeDrag};
+use factory_balancer::model::{Document, Group, NodeMeta, Variable, parse_document, sync_boundaries};
+use factory_balancer::report::totals_report;
+use factory_balancer::stats::{ChartStats, fit_activity_to_input, fit_activity_to_output};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem::take;
+use std::rc::Rc;
 
-use crate::format::format_float;
-use anyhow::{Context as _, Result, anyhow, ensure};
-use eframe::egui::{Align, CentralPanel, Color32, Context, Frame, Key, Modal, Popup, Pos2, RectAlign, TextWrapMode, Ui, Vec2, vec2};
-use eframe::egui::{KeyboardShortcut, Layout, Modifiers, TextEdit, ThemePreference, TopBottomPanel};
-use eframe::{CreationContext, WebRunner};
-use egui_snarl::ui::{PinInfo, PinPlacement, SnarlPin, SnarlStyle, SnarlViewer};
-use egui_snarl::{InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
-use meval::eval_str;
-use serde::{Deserialize, Serialize};
-use std::cell::{Cell, LazyCell};
-use std::{collections::HashMap, ops::Not, rc::Rc};
-use wasm_bindgen::prelude::JsCast;
-use wasm_bindgen_futures::{JsFuture, spawn_local};
-use web_sys::{Storage, window};
-
-const THRESHOLD: f64 = 1E-9;
 const MODAL_WIDTH: f32 = 800.;
-const TOOLTIP_WIDTH: f32 = 160.;
-const STORAGE_PREFIX: &str = "factory-balancer/";
-const BROWN: Color32 = Color32::from_rgb(160, 80, 0);
-
-#[derive(Serialize, Deserialize, Clone)]
-enum NodeMeta {
-    Resource(ResourceMeta),
-    Process(ProcessMeta),
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct ResourceMeta {
-    label: String,
-    #[serde(default, skip_serializing_if = "String::is_empty")]
-    base_rate: String,
-    #[serde(default, skip_serializing_if = "Not::not")]
-    use_base_rate: bool,
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-struct ProcessMeta {
-    label: String,
-    capacity: String,
-    #[serde(default, skip_serializing_if = "String::is_empty")]
-    activity: String,
-    speed: String,
-    inputs: Vec<String>,
-    outputs: Vec<String>,
-}
-
-struct ChartStats {
-    nodes: HashMap<NodeId, NodeStats>,
-}
-
-enum NodeStats {
-    Resource(ResourceStats),
-    Process(ProcessStats),
-}
-
-#[derive(Default, Clone, Copy)]
-struct ResourceStats {
-    invalid: bool,
-    inc: f64,
-    dec: f64,
-    net: f64,
-}
 
-struct ProcessStats {
-    status: ProcessStatus,
-    input_rates: Box<[f64]>,
-    output_rates: Box<[f64]>,
-}
-
-enum ProcessStatus {
-    Invalid,
-    Balanced,
-    Deficient,
-    Excess,
-}
-
-impl ProcessMeta {
-    fn common_rate(&self) -> Option<(f64, ProcessStatus)> {
-        let mut rate = eval_str(&self.capacity).ok()?;
-        let mut status = ProcessStatus::Balanced;
-        if !self.activity.is_empty() {
-            let activity = eval_str(&self.activity).ok()?;
-            let excess = rate - activity;
-            if excess < -2. * THRESHOLD {
-                status = ProcessStatus::Deficient;
-            } else if excess > 1. - THRESHOLD {
-                status = ProcessStatus::Excess;
-            }
-            rate = rate.min(activity);
-        }
-        Some((rate * eval_str(&self.speed).ok()?, status))
+/// Chart of the subfactory at the end of `path`, after dropping the part of the path that no longer exists.
+fn open_chart<'a>(mut chart: &'a mut Snarl<NodeMeta>, path: &mut Vec<NodeId>) -> &'a mut Snarl<NodeMeta> {
+    let (mut valid, mut node) = (0, &*chart);
+    while let Some(&id) = path.get(valid)
+        && let Some(NodeMeta::Subfactory(meta)) = node.get_node(id)
+    {
+        node = &meta.chart;
+        valid += 1;
     }
-}
-
-impl ChartStats {
-    fn compute(chart: &Snarl<NodeMeta>) -> Self {
-        let mut this = Self { nodes: HashMap::new() };
-        for (node, meta) in chart.node_ids() {
-            match meta {
-                NodeMeta::Resource(meta) => {
-                    if meta.use_base_rate {
-                        let stats = this.resource_mut(node);
-                        if let Ok(base_rate) = eval_str(&meta.base_rate) {
-                            stats.net += base_rate;
-                        } else {
-                            stats.invalid = true;
-                        }
-                    }
-                }
-                NodeMeta::Process(meta) => {
-                    let stats = if let Some((rate, mut status)) = meta.common_rate() {
-                        let input_rates = Box::from_iter(meta.inputs.iter().enumerate().map(|(input, qty)| {
-                            let Ok(qty) = eval_str(qty) else { return (0., status = ProcessStatus::Invalid).0 };
-                            let rate = rate * qty;
-                            let adj = <[OutPinId; 1]>::try_from(chart.in_pin(InPinId { node, input }).remotes);
-                            let Ok([adj]) = adj else { return (0., status = ProcessStatus::Invalid).0 };
-                            let stats = this.resource_mut(adj.node);
-                            (stats.dec += rate, stats.net -= rate);
-                            rate
-                        }));
-                        let output_rates = Box::from_iter(meta.outputs.iter().enumerate().map(|(output, qty)| {
-                            let Ok(qty) = eval_str(qty) else { return (0., status = ProcessStatus::Invalid).0 };
-                            let rate = rate * qty;
-                            let adj = <[InPinId; 1]>::try_from(chart.out_pin(OutPinId { node, output }).remotes);
-                            let Ok([adj]) = adj else { return (0., status = ProcessStatus::Invalid).0 };
-                            let stats = this.resource_mut(adj.node);
-                            (stats.inc += rate, stats.net += rate);
-                            rate
-                        }));
-                        ProcessStats { status, input_rates, output_rates }
-                    } else {
-                        ProcessStats { status: ProcessStatus::Invalid, input_rates: <_>::default(), output_rates: <_>::default() }
-                    };
-                    this.nodes.insert(node, NodeStats::Process(stats));
-                }
-            }
-        }
-        this
-    }
-
-    fn resource_mut(&mut self, node: NodeId) -> &mut ResourceStats {
-        let stats = self.nodes.entry(node).or_insert_with(|| NodeStats::Resource(<_>::default()));
-        let NodeStats::Resource(stats) = stats else { unreachable!() };
-        stats
-    }
-
-    fn resource(&self, node: NodeId) -> ResourceStats {
-        if let Some(NodeStats::Resource(stats)) = self.nodes.get(&node) { *stats } else { <_>::default() }
+    path.truncate(valid);
+    for &node in path.iter() {
+        let Some(NodeMeta::Subfactory(meta)) = chart.get_node_mut(node) else { unreachable!() };
+        chart = &mut meta.chart;
     }
+    chart
 }
 
-fn resource_rate_excl_process(chart: &Snarl<NodeMeta>, r: NodeId, p: NodeId) -> f64 {
-    let NodeMeta::Resource(meta) = &chart[r] else { unreachable!() };
-    let mut result = meta.use_base_rate.then(|| eval_str(&meta.base_rate).ok()).flatten().unwrap_or(0.);
-    'outer: for (node, meta) in chart.node_ids() {
-        let false = node == p else { continue };
-        let NodeMeta::Process(meta) = &meta else { continue };
-        let rate = LazyCell::new(|| meta.common_rate().map(|x| x.0));
-        for (input, qty) in meta.inputs.iter().enumerate() {
-            let Ok([adj]) = <[OutPinId; 1]>::try_from(chart.in_pin(InPinId { node, input }).remotes) else { continue };
-            let true = adj.node == r else { continue };
-            let Ok(qty) = eval_str(qty) else { continue };
-            let Some(rate) = *rate else { continue 'outer };
-            result -= rate * qty;
-        }
-        for (output, qty) in meta.outputs.iter().enumerate() {
-            let Ok([adj]) = <[InPinId; 1]>::try_from(chart.out_pin(OutPinId { node, output }).remotes) else { continue };
-            let true = adj.node == r else { continue };
-            let Ok(qty) = eval_str(qty) else { continue };
-            let Some(rate) = *rate else { continue 'outer };
-            result += rate * qty;
-        }
+/// Chart and groups of the subfactory at the end of `path`, which `open_chart` has already validated.
+fn open_groups<'a>(
+    mut chart: &'a mut Snarl<NodeMeta>, mut groups: &'a mut Vec<Group>, path: &[NodeId],
+) -> (&'a mut Snarl<NodeMeta>, &'a mut Vec<Group>) {
+    for &node in path {
+        let Some(NodeMeta::Subfactory(meta)) = chart.get_node_mut(node) else { unreachable!() };
+        (chart, groups) = (&mut meta.chart, &mut meta.groups);
     }
-    result
-}
-
-fn fit_activity_to_input(chart: &Snarl<NodeMeta>, pin: InPinId) -> Option<f64> {
-    let NodeMeta::Process(meta) = &chart[pin.node] else { unreachable!() };
-    let speed = eval_str(&meta.speed).ok()?;
-    let qty = eval_str(&meta.inputs[pin.input]).ok()?;
-    let [r] = <[OutPinId; 1]>::try_from(chart.in_pin(pin).remotes).ok()?;
-    let resource_rate = resource_rate_excl_process(chart, r.node, pin.node);
-    Some(resource_rate / (speed * qty))
+    (chart, groups)
 }
 
-fn fit_activity_to_output(chart: &Snarl<NodeMeta>, pin: OutPinId) -> Option<f64> {
-    let NodeMeta::Process(meta) = &chart[pin.node] else { unreachable!() };
-    let speed = eval_str(&meta.speed).ok()?;
-    let qty = eval_str(&meta.outputs[pin.output]).ok()?;
-    let [r] = <[InPinId; 1]>::try_from(chart.out_pin(pin).remotes).ok()?;
-    let resource_rate = resource_rate_excl_process(chart, r.node, pin.node);
-    Some(-resource_rate / (speed * qty))
+/// Set the activity of a process, or the scale of a subfactory.
+fn set_activity(meta: &mut NodeMeta, activity: f64) {
+    match meta {
+        NodeMeta::Process(meta) => meta.activity = activity.to_string(),
+        NodeMeta::Subfactory(meta) => meta.scale = activity.to_string(),
+        NodeMeta::Resource(_) => unreachable!(),
+    }
 }
 
 /// Return whether to retain.
 type ModalBox = Box<dyn FnMut(&mut App, &Context) -> bool>;
 
-enum Action {
-    None,
-    AddInput(NodeId),
-    AddOutput(NodeId),
-    RemoveInput(InPinId),
-    RemoveOutput(OutPinId),
-    FitActivityToInput(InPinId),
-    FitActivityToOutput(OutPinId),
-    Duplicate(NodeId),
-    Delete(NodeId),
-}
-
-struct ChartViewer {
-    action: Action,
-    stats: ChartStats,
-}
-
-fn prepare_small_button(ui: &mut Ui) {
-    let spacing = &mut ui.style_mut().spacing;
-    spacing.button_padding = Vec2::ZERO;
-    spacing.item_spacing = vec2(1., 0.);
-}
-
-impl SnarlViewer<NodeMeta> for ChartViewer {
-    fn connect(&mut self, from: &OutPin, to: &InPin, chart: &mut Snarl<NodeMeta>) {
-        match (&chart[from.id.node], &chart[to.id.node]) {
-            (NodeMeta::Resource(_), NodeMeta::Resource(_)) => return,
-            (NodeMeta::Process(_), NodeMeta::Process(_)) => return,
-            (NodeMeta::Resource(_), NodeMeta::Process(_)) => {
-                let true = to.remotes.is_empty() else { return };
-            }
-            (NodeMeta::Process(_), NodeMeta::Resource(_)) => {
-                let true = from.remotes.is_empty() else { return };
-            }
-        }
-        chart.connect(from.id, to.id);
-    }
-
-    fn title(&mut self, meta: &NodeMeta) -> String {
-        match meta {
-            NodeMeta::Resource(meta) => meta.label.clone(),
-            NodeMeta::Process(meta) => meta.label.clone(),
-        }
-    }
-
-    fn show_header(&mut self, node: NodeId, _: &[InPin], _: &[OutPin], ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
-        let (width, label) = match &mut chart[node] {
-            NodeMeta::Resource(meta) => (80., &mut meta.label),
-            NodeMeta::Process(meta) => {
-                let mut width = 108.;
-                (!meta.inputs.is_empty()).then(|| width += 36.);
-                (!meta.outputs.is_empty()).then(|| width += 36.);
-                (width, &mut meta.label)
-            }
-        };
-        ui.set_width(width);
-        TextEdit::singleline(label).desired_width(f32::INFINITY).show(ui);
-    }
-
-    fn node_frame(&mut self, mut frame: Frame, node: NodeId, _: &[InPin], _: &[OutPin], _: &Snarl<NodeMeta>) -> Frame {
-        let Some(stats) = self.stats.nodes.get(&node) else { return frame };
-        match stats {
-            NodeStats::Process(stats) => match stats.status {
-                ProcessStatus::Invalid => frame.fill = Color32::DARK_RED,
-                ProcessStatus::Balanced => (),
-                ProcessStatus::Deficient => frame.fill = BROWN,
-                ProcessStatus::Excess => frame.fill = Color32::DARK_GREEN,
-            },
-            NodeStats::Resource(stats) => {
-                if stats.invalid {
-                    frame.fill = Color32::DARK_RED;
-                } else if stats.net < -THRESHOLD {
-                    frame.fill = BROWN;
-                } else if stats.net > THRESHOLD {
-                    frame.fill = Color32::DARK_GREEN;
-                }
-            }
-        }
-        frame
-    }
-
-    fn has_body(&mut self, _: &NodeMeta) -> bool { true }
-    fn show_body(&mut self, node: NodeId, _: &[InPin], _: &[OutPin], ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
-        match &mut chart[node] {
-            NodeMeta::Resource(meta) => {
-                ui.set_width(72.);
-                ui.vertical_centered(|ui| {
-                    meta.use_base_rate.then(|| TextEdit::singleline(&mut meta.base_rate).desired_width(f32::INFINITY).show(ui));
-                    let stats = self.stats.resource(node);
-                    let inc = format_float(stats.inc, THRESHOLD);
-                    let dec = format_float(stats.dec, THRESHOLD);
-                    let net = format_float(stats.net, THRESHOLD);
-                    ui.label(format!("➕ {inc}\n➖ {dec}\nNet {net}"));
-                });
-            }
-            NodeMeta::Process(meta) => {
-                ui.set_width(100.);
-                ui.vertical(|ui| {
-                    ui.horizontal(|ui| {
-                        ui.label("Cap");
-                        TextEdit::singleline(&mut meta.capacity).desired_width(f32::INFINITY).show(ui);
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Act");
-                        TextEdit::singleline(&mut meta.activity).desired_width(f32::INFINITY).show(ui);
-                    });
-                    ui.horizontal(|ui| {
-                        ui.label("Spd");
-                        TextEdit::singleline(&mut meta.speed).desired_width(f32::INFINITY).show(ui);
-                    });
-                    ui.horizontal(|ui| {
-                        prepare_small_button(ui);
-                        ui.small_button("➕").clicked().then(|| self.action = Action::AddInput(node));
-                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-                            ui.small_button("➕").clicked().then(|| self.action = Action::AddOutput(node));
-                        });
-                    });
-                });
-            }
-        }
-    }
-
-    fn inputs(&mut self, meta: &NodeMeta) -> usize {
-        match meta {
-            NodeMeta::Resource(_) => 1,
-            NodeMeta::Process(meta) => meta.inputs.len(),
-        }
-    }
-
-    fn show_input(&mut self, pin: &InPin, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) -> impl SnarlPin + 'static {
-        if let NodeMeta::Process(meta) = &mut chart[pin.id.node] {
-            ui.vertical(|ui| {
-                TextEdit::singleline(&mut meta.inputs[pin.id.input]).desired_width(20.).show(ui);
-                ui.horizontal(|ui| {
-                    prepare_small_button(ui);
-                    ui.small_button("✖").clicked().then(|| self.action = Action::RemoveInput(pin.id));
-                    ui.small_button("➡").clicked().then(|| self.action = Action::FitActivityToInput(pin.id));
-                });
-            });
-        }
-        if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&pin.id.node)
-            && let Some(pos) = ui.input(|x| x.pointer.hover_pos())
-            && let rect = ui.min_rect().intersect(ui.clip_rect())
-            && let rect = ui.ctx().layer_transform_to_global(ui.layer_id()).map_or(rect, |t| t * rect)
-            && rect.contains(pos)
-        {
-            let mut msg = String::new();
-            if pin.remotes.len() == 1 {
-                let NodeMeta::Resource(meta) = &chart[pin.remotes[0].node] else { unreachable!() };
-                msg.clone_from(&meta.label);
-            }
-            if let Some(rate) = stats.input_rates.get(pin.id.input) {
-                (!msg.is_empty()).then(|| msg += ": ");
-                msg += &format_float(*rate, THRESHOLD);
-            }
-            if !msg.is_empty() {
-                Popup::new(ui.id(), ui.ctx().clone(), rect, ui.layer_id()).align(RectAlign::LEFT).show(|ui| {
-                    ui.set_max_width(TOOLTIP_WIDTH);
-                    ui.label(msg);
-                });
-            }
-        }
-        PinInfo::square()
-    }
-
-    fn outputs(&mut self, meta: &NodeMeta) -> usize {
-        match meta {
-            NodeMeta::Resource(_) => 1,
-            NodeMeta::Process(meta) => meta.outputs.len(),
-        }
-    }
-
-    fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) -> impl SnarlPin + 'static {
-        if let NodeMeta::Process(meta) = &mut chart[pin.id.node] {
-            ui.set_width(30.);
-            ui.vertical(|ui| {
-                TextEdit::singleline(&mut meta.outputs[pin.id.output]).desired_width(20.).show(ui);
-                ui.horizontal(|ui| {
-                    prepare_small_button(ui);
-                    ui.small_button("⬅").clicked().then(|| self.action = Action::FitActivityToOutput(pin.id));
-                    ui.small_button("✖").clicked().then(|| self.action = Action::RemoveOutput(pin.id));
-                });
-            });
-        }
-        if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&pin.id.node)
-            && let Some(pos) = ui.input(|x| x.pointer.hover_pos())
-            && let rect = ui.min_rect().intersect(ui.clip_rect())
-            && let rect = ui.ctx().layer_transform_to_global(ui.layer_id()).map_or(rect, |t| t * rect)
-            && rect.contains(pos)
-        {
-            let mut msg = String::new();
-            if pin.remotes.len() == 1 {
-                let NodeMeta::Resource(meta) = &chart[pin.remotes[0].node] else { unreachable!() };
-                msg.clone_from(&meta.label);
-            }
-            if let Some(rate) = stats.output_rates.get(pin.id.output) {
-                (!msg.is_empty()).then(|| msg += ": ");
-                msg += &format_float(*rate, THRESHOLD);
-            }
-            if !msg.is_empty() {
-                Popup::new(ui.id(), ui.ctx().clone(), rect, ui.layer_id()).align(RectAlign::RIGHT).show(|ui| {
-                    ui.set_max_width(TOOLTIP_WIDTH);
-                    ui.label(msg);
-                });
-            }
-        }
-        PinInfo::square()
-    }
-
-    fn has_graph_menu(&mut self, _: Pos2, _: &mut Snarl<NodeMeta>) -> bool { true }
-    fn show_graph_menu(&mut self, pos: Pos2, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
-        ui.button("New Resource").clicked().then(|| {
-            chart.insert_node(pos, NodeMeta::Resource(ResourceMeta { label: String::new(), base_rate: String::new(), use_base_rate: false }));
-        });
-        ui.button("New Process").clicked().then(|| {
-            let meta = ProcessMeta {
-                label: String::new(),
-                capacity: "1".to_owned(),
-                activity: String::new(),
-                speed: "1".to_owned(),
-                inputs: vec!["1".to_owned()],
-                outputs: vec!["1".to_owned()],
-            };
-            chart.insert_node(pos, NodeMeta::Process(meta));
-        });
-    }
-
-    fn has_node_menu(&mut self, _: &NodeMeta) -> bool { true }
-    fn show_node_menu(&mut self, node: NodeId, _: &[InPin], _: &[OutPin], ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
-        ui.button("Delete").clicked().then(|| self.action = Action::Delete(node));
-        ui.button("Duplicate").clicked().then(|| self.action = Action::Duplicate(node));
-        if let NodeMeta::Resource(meta) = &mut chart[node] {
-            ui.checkbox(&mut meta.use_base_rate, "Enable Base Rate");
-        }
-    }
-}
-
 struct App {
     style: SnarlStyle,
-    chart: Snarl<NodeMeta>,
+    doc: Document,
+    history: History,
     modal: Option<ModalBox>,
-    storage: Option<Storage>,
+    storage: Option<Box<dyn Storage>>,
     storage_key: String,
+    integer_mode: bool,
+    display_unit: TimeUnit,
+    library: Library,
+    show_library: bool,
+    show_sankey: bool,
+    show_summary: bool,
+    summary: Summary,
+    /// Node to pan the view to in the next frame.
+    pan_to: Option<NodeId>,
+    /// Subfactories opened, from the top chart down to the one being edited.
+    path: Vec<NodeId>,
+    /// Rect of each node relative to its position, as last drawn.
+    node_rects: HashMap<NodeId, Rect>,
+    /// Total power drawn by the open chart, if shown. The top panel comes first, so this is from the last frame's stats.
+    power: Option<f64>,
+    connect_resources: bool,
+    flow_wires: bool,
+    pins: PinPositions,
 }
 
 impl App {
@@ -454,6 +115,17 @@ impl App {
         }));
     }
 
+    fn show_totals(&mut self, report: String) {
+        self.modal = Some(Box::new(move |_, ctx| {
+            let resp = Modal::new("totals".into()).show(ctx, |ui| {
+                ui.set_max_width(MODAL_WIDTH);
+                ui.label(&report);
+                ui.button("Copy").clicked().then(|| ctx.copy_text(report.clone()));
+            });
+            !resp.should_close()
+        }));
+    }
+
     fn show_storage_key_list(&mut self, mut keys: Vec<String>) {
         self.modal = Some(Box::new(move |app, ctx| {
             enum Action<'a> {
@@ -480,8 +152,7 @@ impl App {
                     return false;
                 }
                 Action::Delete(i) => {
-                    let key = format!("{STORAGE_PREFIX}{}", keys.remove(i));
-                    if let Err(e) = app.storage.as_ref().unwrap().remove_item(&key) {
+                    if let Err(e) = app.storage.as_ref().unwrap().delete(&keys.remove(i)) {
                         app.alert(format!("{e:?}"));
                     }
                 }
@@ -490,24 +161,83 @@ impl App {
         }));
     }
 
+    fn show_variables(&mut self) {
+        self.modal = Some(Box::new(|app, ctx| {
+            let env = Env::new(&app.doc.variables);
+            let variables = &mut app.doc.variables;
+            let resp = Modal::new("variables".into()).show(ctx, |ui| {
+                ui.set_max_width(MODAL_WIDTH);
+                let mut remove = None;
+                for (i, var) in variables.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.button("✖").clicked().then(|| remove = Some(i));
+                        TextEdit::singleline(&mut var.name).desired_width(120.).show(ui);
+                        ui.label("=");
+                        TextEdit::singleline(&mut var.expr).desired_width(240.).show(ui);
+                        match env.variables.get(&var.name) {
+                            Some(Ok(value)) => drop(ui.label(format_float(*value, THRESHOLD))),
+                            Some(Err(e)) => drop(ui.colored_label(Color32::RED, e)),
+                            None => (),
+                        }
+                    });
+                }
+                remove.map(|i| variables.remove(i));
+                ui.button("➕").clicked().then(|| variables.push(Variable { name: String::new(), expr: String::new() }));
+            });
+            !resp.should_close()
+        }));
+    }
+
+    fn show_optimizer(&mut self) {
+        let mut objective = Objective::MinimizeMachines;
+        let mut report = String::new();
+        self.modal = Some(Box::new(move |app, ctx| {
+            let resp = Modal::new("optimizer".into()).show(ctx, |ui| {
+                ui.set_max_width(MODAL_WIDTH);
+                ui.horizontal(|ui| {
+                    ui.label("Objective:");
+                    let text = match objective {
+                        Objective::MaximizeResource(r) => match open_chart(&mut app.doc.chart, &mut app.path).get_node(r) {
+                            Some(NodeMeta::Resource(meta)) => format!("Maximize {}", meta.label),
+                            _ => (objective = Objective::MinimizeMachines, "Minimize Total Activity".to_owned()).1,
+                        },
+                        Objective::MinimizeMachines => "Minimize Total Activity".to_owned(),
+                        Objective::MinimizeRawDraw => "Minimize Raw Resource Draw".to_owned(),
+                    };
+                    ComboBox::from_id_salt("objective").selected_text(text).show_ui(ui, |ui| {
+                        ui.selectable_value(&mut objective, Objective::MinimizeMachines, "Minimize Total Activity");
+                        ui.selectable_value(&mut objective, Objective::MinimizeRawDraw, "Minimize Raw Resource Draw");
+                        for (node, meta) in open_chart(&mut app.doc.chart, &mut app.path).node_ids() {
+                            let NodeMeta::Resource(meta) = meta else { continue };
+                            ui.selectable_value(&mut objective, Objective::MaximizeResource(node), format!("Maximize {}", meta.label));
+                        }
+                    });
+                    ui.button("Run").clicked().then(|| {
+                        let fmt = RateFormat::new(app.doc.time_unit, app.display_unit);
+                        let env = Env::new(&app.doc.variables);
+                        report = optimize(open_chart(&mut app.doc.chart, &mut app.path), &env, fmt, objective)
+                    });
+                });
+                if !report.is_empty() {
+                    ui.separator();
+                    ui.label(&report);
+                }
+            });
+            !resp.should_close()
+        }));
+    }
+
     fn load_from_storage(&mut self) {
         if let Err(e) = (|| -> Result<()> {
             let storage = self.storage.as_ref().unwrap();
             if self.storage_key.is_empty() {
-                let len = storage.length().map_err(|e| anyhow!("{e:?}"))?;
-                let mut keys = Vec::new();
-                for i in 0..len {
-                    let key = storage.key(i).ok().flatten().context("Failed to list storage keys")?;
-                    let Some(key) = key.strip_prefix(STORAGE_PREFIX) else { continue };
-                    let false = key.is_empty() else { continue };
-                    keys.push(key.to_owned());
-                }
+                let mut keys = storage.list()?;
                 keys.sort_unstable();
-                return Ok(self.show_storage_key_list(keys));
+                self.show_storage_key_list(keys);
+                return Ok(());
             }
-            let key = format!("{STORAGE_PREFIX}{}", self.storage_key);
-            let data = storage.get_item(&key).ok().flatten().context("Item not found")?;
-            Ok(self.chart = ron::from_str(&data)?)
+            self.doc = parse_document(&storage.load(&self.storage_key)?)?;
+            Ok(())
         })() {
             self.alert(format!("{e:?}"));
         }
@@ -516,39 +246,52 @@ impl App {
     fn save_to_storage(&mut self) {
         if let Err(e) = (|| -> Result<()> {
             ensure!(!self.storage_key.is_empty(), "Storage key shouldn't be empty");
-            let data = ron::to_string(&self.chart)?;
-            let key = format!("{STORAGE_PREFIX}{}", self.storage_key);
-            self.storage.as_ref().unwrap().set_item(&key, &data).map_err(|e| anyhow!("{e:?}"))
+            self.storage.as_ref().unwrap().save(&self.storage_key, &ron::to_string(&self.doc)?)
         })() {
             self.alert(format!("{e:?}"));
         }
     }
 
-    fn load_from_clipboard(&mut self, ctx: Context) {
-        let data = JsFuture::from(window().unwrap().navigator().clipboard().read_text());
-        let slot = Rc::new(Cell::new(None::<Result<String>>));
+    fn save_library(&mut self) {
+        let Some(storage) = &self.storage else { return };
+        if let Err(e) = (|| -> Result<()> { storage.save_library(&ron::to_string(&self.library)?) })() {
+            self.alert(format!("{e:?}"));
+        }
+    }
+
+    /// Show a waiting message until `data` is ready, then pass it to `apply`.
+    fn load_async<T: 'static>(
+        &mut self, ctx: Context, msg: &'static str, data: impl Future<Output = Result<T>> + 'static,
+        apply: impl FnOnce(&mut App, T) -> Result<()> + 'static,
+    ) {
+        let mut apply = Some(apply);
+        let slot = Rc::new(Cell::new(None::<Result<T>>));
         let weak = Rc::downgrade(&slot);
         self.modal = Some(Box::new(move |app, ctx| {
             let Some(data) = slot.take() else {
-                Modal::new("wait_for_clipboard".into()).show(ctx, |ui| ui.label("Waiting for clipboard"));
-                return true;
+                return !Modal::new("wait_for_data".into()).show(ctx, |ui| ui.label(msg)).should_close();
             };
-            if let Err(e) = (|| -> Result<()> { Ok(app.chart = ron::from_str(&data?)?) })() {
+            if let Err(e) = data.and_then(|data| apply.take().unwrap()(app, data)) {
                 app.alert(format!("{e:?}"));
             }
+            app.history.dirty = true;
             false
         }));
-        spawn_local(async move {
+        spawn(async move {
             let data = data.await;
             let Some(slot) = weak.upgrade() else { return };
-            slot.set(Some(data.map_err(|e| anyhow!("{e:?}")).map(|x| x.as_string().context("Not a string")).flatten()));
+            slot.set(Some(data));
             ctx.request_repaint();
         });
     }
 
-    fn save_to_clipboard(&mut self) {
-        match ron::to_string(&self.chart) {
-            Ok(data) => drop(window().unwrap().navigator().clipboard().write_text(&data)),
+    fn load_from_clipboard(&mut self, ctx: Context, apply: impl FnOnce(&mut App, String) -> Result<()> + 'static) {
+        self.load_async(ctx, "Waiting for clipboard", read_clipboard(), apply);
+    }
+
+    fn save_to_clipboard(&mut self, ctx: &Context, data: ron::Result<String>) {
+        match data {
+            Ok(data) => ctx.copy_text(data),
             Err(e) => self.alert(e.to_string()),
         }
     }
@@ -556,15 +299,27 @@ impl App {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &Context, _: &mut eframe::Frame) {
+        // Edits are grouped until the pointer is released and no text field has focus. Only input or a load can edit the
+        // document, so it's only compared with the last snapshot after those.
+        let settled = !ctx.input(|x| x.pointer.any_down()) && ctx.memory(|x| x.focused().is_none());
+        settled.then(|| self.history.commit(&self.doc));
+        let edits =
+            |x: &Event| !matches!(x, Event::PointerMoved(_) | Event::MouseMoved(_) | Event::PointerGone | Event::Zoom(_) | Event::WindowFocused(_));
+        self.history.dirty |= ctx.input(|x| x.events.iter().any(edits));
         TopBottomPanel::top("top").show(ctx, |ui| {
             ui.horizontal(|ui| {
-                ui.button("Source").clicked().then(|| {
-                    if let Err(e) = window().unwrap().open_with_url_and_target("https://github.com/cyb0124/factory-balancer/", "_blank") {
-                        self.alert(format!("{e:?}"));
-                    }
+                ui.button("Source").clicked().then(|| ctx.open_url(OpenUrl::new_tab("https://github.com/cyb0124/factory-balancer/")));
+                ui.separator();
+                let shortcuts = settled.then(|| {
+                    let redo = ui.input_mut(|x| x.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL | Modifiers::SHIFT, Key::Z)));
+                    (!redo && ui.input_mut(|x| x.consume_shortcut(&KeyboardShortcut::new(Modifiers::CTRL, Key::Z))), redo)
                 });
+                let (undo, redo) = shortcuts.unwrap_or_default();
+                (ui.add_enabled(!self.history.undo.is_empty(), Button::new("Undo")).clicked() || undo).then(|| self.history.undo(&mut self.doc));
+                (ui.add_enabled(!self.history.redo.is_empty(), Button::new("Redo")).clicked() || redo).then(|| self.history.redo(&mut self.doc));
                 ui.separator();
-                ui.label("Browser Storage:");
+                let env = Env::new(&self.doc.variables);
+                ui.label("Storage:");
                 if self.storage.is_some() {
                     TextEdit::singleline(&mut self.storage_key).desired_width(120.).show(ui);
                     ui.button("Load").clicked().then(|| self.load_from_storage());
@@ -575,67 +330,247 @@ impl eframe::App for App {
                 }
                 ui.separator();
                 ui.label("Clipboard:");
-                ui.button("Load").clicked().then(|| self.load_from_clipboard(ctx.clone()));
-                ui.button("Save").clicked().then(|| self.save_to_clipboard());
+                ui.button("Load").clicked().then(|| {
+                    self.load_from_clipboard(ctx.clone(), |app, data| {
+                        app.doc = parse_document(&data)?;
+                        Ok(())
+                    })
+                });
+                ui.button("Save").clicked().then(|| self.save_to_clipboard(ctx, ron::to_string(&self.doc)));
+                ui.separator();
+                ui.label("Time Unit:");
+                let unit_text = |x: Option<TimeUnit>| x.map_or("(none)", |x| x.suffix());
+                ComboBox::from_id_salt("time_unit").selected_text(unit_text(self.doc.time_unit)).width(60.).show_ui(ui, |ui| {
+                    for unit in [None].into_iter().chain(TimeUnit::ALL.map(Some)) {
+                        if ui.selectable_value(&mut self.doc.time_unit, unit, unit_text(unit)).clicked()
+                            && let Some(unit) = unit
+                        {
+                            self.display_unit = unit;
+                        }
+                    }
+                });
+                if self.doc.time_unit.is_some() {
+                    ui.label("Display:");
+                    ComboBox::from_id_salt("display_unit").selected_text(self.display_unit.suffix()).width(60.).show_ui(ui, |ui| {
+                        TimeUnit::ALL.into_iter().for_each(|x| drop(ui.selectable_value(&mut self.display_unit, x, x.suffix())));
+                    });
+                }
+                ui.separator();
+                ui.toggle_value(&mut self.show_library, "Library");
+                ui.toggle_value(&mut self.show_sankey, "Sankey");
+                ui.toggle_value(&mut self.show_summary, "Summary");
+                ui.button("Variables").clicked().then(|| self.show_variables());
+                ui.button("Balance All").clicked().then(|| {
+                    let report = balance_all(open_chart(&mut self.doc.chart, &mut self.path), &env);
+                    self.alert(report);
+                });
+                ui.button("Optimize").clicked().then(|| self.show_optimizer());
+                ui.button("Totals").clicked().then(|| {
+                    let fmt = RateFormat::new(self.doc.time_unit, self.display_unit);
+                    let chart = open_chart(&mut self.doc.chart, &mut self.path);
+                    let report = totals_report(chart, &ChartStats::compute(chart, &env), fmt);
+                    self.show_totals(report)
+                });
+                self.power.map(|x| ui.label(format!("Power: {}", format_float(x, THRESHOLD))));
+                ui.separator();
+                ui.checkbox(&mut self.flow_wires, "Flow Wires");
+                ui.checkbox(&mut self.integer_mode, "Integer Machines");
+                if self.integer_mode {
+                    ui.button("Round Up Capacities").clicked().then(|| round_up_capacities(open_chart(&mut self.doc.chart, &mut self.path), &env));
+                    ui.button("Tight Integer").clicked().then(|| {
+                        let report = tight_integer(open_chart(&mut self.doc.chart, &mut self.path), &env);
+                        self.alert(report);
+                    });
+                }
             });
         });
+        if self.show_library {
+            SidePanel::left("library").show(ctx, |ui| {
+                ui.checkbox(&mut self.connect_resources, "Create Resources");
+                ui.horizontal(|ui| {
+                    ui.label("Clipboard:");
+                    ui.button("Import").clicked().then(|| {
+                        self.load_from_clipboard(ctx.clone(), |app, data| {
+                            ron::from_str::<Library>(&data)?.recipes.into_iter().for_each(|x| app.library.insert(x));
+                            app.save_library();
+                            Ok(())
+                        })
+                    });
+                    ui.button("Export").clicked().then(|| self.save_to_clipboard(ctx, ron::to_string(&self.library)));
+                });
+                ui.button("Import Game Data").clicked().then(|| {
+                    self.load_async(ctx.clone(), "Waiting for file", pick_file(), |app, data| {
+                        let Some(data) = data else { return Ok(()) };
+                        let import = import_recipes(&data)?;
+                        let mut msg = format!("Imported {} recipes", import.recipes.len());
+                        if !import.choices.is_empty() {
+                            msg += "\n\nUsing the fastest machine where there's a choice; edit the crafting speed of a process to use another:";
+                            import.choices.iter().for_each(|x| msg += &format!("\n{x}"));
+                        }
+                        import.recipes.into_iter().for_each(|x| app.library.insert(x));
+                        app.save_library();
+                        app.alert(msg);
+                        Ok(())
+                    })
+                });
+                ui.separator();
+                let false = self.library.recipes.is_empty() else { return drop(ui.label("(Empty)")) };
+                let mut remove = None;
+                for (i, recipe) in self.library.recipes.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.button("✖").clicked().then(|| remove = Some(i));
+                        ui.dnd_drag_source(ui.id().with(i), RecipeDrag(i), |ui| ui.label(&recipe.process.label))
+                            .response
+                            .on_hover_text(recipe.summary());
+                    });
+                }
+                if let Some(i) = remove {
+                    self.library.recipes.remove(i);
+                    self.save_library();
+                }
+            });
+        }
+        sync_boundaries(&mut self.doc.chart);
+        if !self.path.is_empty() {
+            TopBottomPanel::top("path").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let mut open = None;
+                    ui.button("Top").clicked().then(|| open = Some(0));
+                    let mut chart = &self.doc.chart;
+                    for (i, &node) in self.path.iter().enumerate() {
+                        let NodeMeta::Subfactory(meta) = &chart[node] else { unreachable!() };
+                        ui.label("›");
+                        let label = if meta.label.is_empty() { "(unnamed)" } else { &meta.label };
+                        ui.add_enabled(i + 1 < self.path.len(), Button::new(label)).clicked().then(|| open = Some(i + 1));
+                        chart = &meta.chart;
+                    }
+                    if let Some(x) = open {
+                        self.path.truncate(x)
+                    }
+                });
+            });
+        }
+        let env = Env::new(&self.doc.variables);
+        let chart = open_chart(&mut self.doc.chart, &mut self.path);
+        let stats = ChartStats::compute(chart, &env);
+        let has_power = chart.nodes().any(|x| matches!(x, NodeMeta::Process(x) if !x.power.is_empty() || !x.idle_power.is_empty()));
+        // Subfactories only count if their charts draw power.
+        let has_subfactory = chart.nodes().any(|x| matches!(x, NodeMeta::Subfactory(_)));
+        let power = (has_power || has_subfactory && stats.power != 0.).then_some(stats.power);
+        if power != self.power {
+            self.power = power;
+            ctx.request_repaint();
+        }
+        let fmt = RateFormat::new(self.doc.time_unit, self.display_unit);
+        if self.show_summary {
+            SidePanel::right("summary").show(ctx, |ui| {
+                let Some(node) = self.summary.show(ui, open_chart(&mut self.doc.chart, &mut self.path), &stats, &fmt) else { return };
+                self.pan_to = Some(node);
+                self.show_sankey = false;
+            });
+        }
         CentralPanel::default().show(ctx, |ui| {
-            let stats = ChartStats::compute(&self.chart);
-            let mut viewer = ChartViewer { action: Action::None, stats };
-            self.chart.show(&mut viewer, &self.style, (), ui);
-            match viewer.action {
+            let (chart, groups) = open_groups(&mut self.doc.chart, &mut self.doc.groups, &self.path);
+            let false = self.show_sankey else { return show_sankey(ui, chart, &stats, &fmt) };
+            sync_groups(chart, groups);
+            let id = ui.make_persistent_id(&self.path);
+            let mut viewer = ChartViewer {
+                action: Action::None,
+                library: &self.library,
+                connect_resources: self.connect_resources,
+                to_global: TSTransform::IDENTITY,
+                // Roughly the center of the node.
+                pan: self.pan_to.take().and_then(|x| chart.get_node_info(x)).map(|x| (x.pos + vec2(80., 40.), ui.max_rect().center())),
+                stats,
+                integer_mode: self.integer_mode,
+                fmt,
+                flow_wires: self.flow_wires,
+                pins: self.pins.clone(),
+                drawn_pins: HashMap::new(),
+                groups,
+                selected: get_selected_nodes(id, ctx),
+                node_rects: take(&mut self.node_rects),
+            };
+            chart.show(&mut viewer, &self.style, &self.path, ui);
+            (self.flow_wires && viewer.drawn_pins != *self.pins.borrow()).then(|| ctx.request_repaint());
+            if ui.input(|x| x.pointer.any_released())
+                && let Some(pos) = ui.input(|x| x.pointer.interact_pos())
+                && ui.max_rect().contains(pos)
+                && let Some(drag) = DragAndDrop::take_payload::<RecipeDrag>(ctx)
+                && let Some(recipe) = self.library.recipes.get(drag.0)
+            {
+                recipe.instantiate(chart, viewer.to_global.inverse() * pos, self.connect_resources);
+            }
+            let ChartViewer { action, stats, fmt, to_global, node_rects, .. } = viewer;
+            show_groups(ui, chart, groups, &stats, &fmt, &node_rects, to_global);
+            self.node_rects = node_rects;
+            match action {
                 Action::None => (),
                 Action::AddInput(node) => {
-                    let NodeMeta::Process(meta) = &mut self.chart[node] else { unreachable!() };
+                    let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
                     meta.inputs.push("1".to_owned());
                 }
                 Action::AddOutput(node) => {
-                    let NodeMeta::Process(meta) = &mut self.chart[node] else { unreachable!() };
+                    let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
                     meta.outputs.push("1".to_owned());
                 }
                 Action::RemoveInput(pin) => {
-                    let NodeMeta::Process(meta) = &mut self.chart[pin.node] else { unreachable!() };
+                    let NodeMeta::Process(meta) = &mut chart[pin.node] else { unreachable!() };
                     let old_len = meta.inputs.len();
                     meta.inputs.remove(pin.input);
-                    self.chart.drop_inputs(pin);
+                    (pin.input < meta.input_weights.len()).then(|| meta.input_weights.remove(pin.input));
+                    (pin.input < meta.input_returns.len()).then(|| meta.input_returns.remove(pin.input));
+                    chart.drop_inputs(pin);
                     for i in pin.input + 1..old_len {
                         let old = InPinId { node: pin.node, input: i };
                         let new = InPinId { node: pin.node, input: i - 1 };
-                        self.chart.in_pin(old).remotes.into_iter().for_each(|far| _ = self.chart.connect(far, new));
+                        chart.in_pin(old).remotes.into_iter().for_each(|far| _ = chart.connect(far, new));
                     }
                 }
                 Action::RemoveOutput(pin) => {
-                    let NodeMeta::Process(meta) = &mut self.chart[pin.node] else { unreachable!() };
+                    let NodeMeta::Process(meta) = &mut chart[pin.node] else { unreachable!() };
                     let old_len = meta.outputs.len();
                     meta.outputs.remove(pin.output);
-                    self.chart.drop_outputs(pin);
+                    (pin.output < meta.output_weights.len()).then(|| meta.output_weights.remove(pin.output));
+                    (pin.output < meta.output_chances.len()).then(|| meta.output_chances.remove(pin.output));
+                    chart.drop_outputs(pin);
                     for i in pin.output + 1..old_len {
                         let old = OutPinId { node: pin.node, output: i };
                         let new = OutPinId { node: pin.node, output: i - 1 };
-                        self.chart.out_pin(old).remotes.into_iter().for_each(|far| _ = self.chart.connect(new, far));
+                        chart.out_pin(old).remotes.into_iter().for_each(|far| _ = chart.connect(new, far));
                     }
                 }
                 Action::FitActivityToInput(pin) => {
-                    if let Some(activity) = fit_activity_to_input(&self.chart, pin) {
-                        let NodeMeta::Process(meta) = &mut self.chart[pin.node] else { unreachable!() };
-                        meta.activity = activity.to_string();
+                    if let Some(activity) = fit_activity_to_input(chart, &stats, pin) {
+                        set_activity(&mut chart[pin.node], activity);
                     } else {
                         self.alert("Failed to compute".to_owned());
                     }
                 }
                 Action::FitActivityToOutput(pin) => {
-                    if let Some(activity) = fit_activity_to_output(&self.chart, pin) {
-                        let NodeMeta::Process(meta) = &mut self.chart[pin.node] else { unreachable!() };
-                        meta.activity = activity.to_string();
+                    if let Some(activity) = fit_activity_to_output(chart, &stats, pin) {
+                        set_activity(&mut chart[pin.node], activity);
                     } else {
                         self.alert("Failed to compute".to_owned());
                     }
                 }
                 Action::Duplicate(node) => {
-                    let node = self.chart.get_node_info(node).unwrap();
-                    self.chart.insert_node(node.pos + vec2(32., 32.), node.value.clone());
+                    let node = chart.get_node_info(node).unwrap();
+                    chart.insert_node(node.pos + vec2(32., 32.), node.value.clone());
+                }
+                Action::Delete(node) => drop(chart.remove_node(node)),
+                Action::Open(node) => self.path.push(node),
+                Action::Group(nodes) => new_group(groups, nodes),
+                Action::Ungroup(node) => groups.iter_mut().for_each(|x| _ = x.nodes.remove(&node)),
+                Action::AddToLibrary(node) => {
+                    let recipe = Recipe::from_process(chart, node);
+                    if recipe.process.label.is_empty() {
+                        self.alert("Recipes need a label".to_owned());
+                    } else {
+                        self.library.insert(recipe);
+                        self.save_library();
+                    }
                 }
-                Action::Delete(node) => drop(self.chart.remove_node(node)),
             }
         });
         if let Some(mut modal) = self.modal.take() {
@@ -651,13 +586,43 @@ fn make_app(cc: &CreationContext) -> App {
         header_drag_space: Some(Vec2::ZERO),
         collapsible: Some(false),
         wire_width: Some(3.),
+        wire_frame_size: Some(WIRE_FRAME_SIZE),
         pin_placement: Some(PinPlacement::Edge),
         ..<_>::default()
     };
-    App { style, chart: Snarl::new(), modal: None, storage: window().unwrap().local_storage().ok().flatten(), storage_key: String::new() }
+    let storage = storage::open();
+    let library = storage.as_ref().and_then(|x| x.load_library()).and_then(|x| ron::from_str(&x).ok());
+    let doc = Document { chart: Snarl::new(), variables: Vec::new(), time_unit: None, groups: Vec::new() };
+    App {
+        style,
+        history: History::new(&doc),
+        doc,
+        modal: None,
+        storage,
+        storage_key: String::new(),
+        integer_mode: false,
+        display_unit: TimeUnit::Second,
+        library: library.unwrap_or_default(),
+        show_library: false,
+        show_sankey: false,
+        show_summary: false,
+        summary: <_>::default(),
+        pan_to: None,
+        path: Vec::new(),
+        node_rects: HashMap::new(),
+        power: None,
+        connect_resources: true,
+        flow_wires: false,
+        pins: <_>::default(),
+    }
 }
 
+#[cfg(target_arch = "wasm32")]
 fn main() {
-    let canvas = window().unwrap().document().unwrap().get_element_by_id("main").unwrap().unchecked_into();
-    spawn_local(async { WebRunner::new().start(canvas, <_>::default(), Box::new(|cc| Ok(Box::new(make_app(cc))))).await.unwrap() });
+    use wasm_bindgen::prelude::JsCast;
+    let canvas = web_sys::window().unwrap().document().unwrap().get_element_by_id("main").unwrap().unchecked_into();
+    spawn(async { eframe::WebRunner::new().start(canvas, <_>::default(), Box::new(|cc| Ok(Box::new(make_app(cc))))).await.unwrap() });
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() -> eframe::Result { eframe::run_native("Factory Balancer", <_>::default(), Box::new(|cc| Ok(Box::new(make_app(cc))))) }
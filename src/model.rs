@@ -0,0 +1,240 @@
+use crate::format::TimeUnit;
+use anyhow::Result;
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Not;
+
+/// Everything that gets saved.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Document {
+    pub chart: Snarl<NodeMeta>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variables: Vec<Variable>,
+    /// Time unit in which speeds and base rates are entered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_unit: Option<TimeUnit>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+}
+
+/// Labelled frame around nodes of a chart, which move together and can be collapsed into a summary of their flows.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Group {
+    pub label: String,
+    pub color: [u8; 3],
+    pub nodes: BTreeSet<NodeId>,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub collapsed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Variable {
+    pub name: String,
+    pub expr: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum NodeMeta {
+    Resource(ResourceMeta),
+    Process(Box<ProcessMeta>),
+    Subfactory(Box<SubfactoryMeta>),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ResourceMeta {
+    pub label: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub base_rate: String,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub use_base_rate: bool,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub nonnegative: bool,
+    /// Supplies the power drawn by every process, as if wired to all of them.
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub power: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ProcessMeta {
+    pub label: String,
+    pub capacity: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub activity: String,
+    pub speed: String,
+    /// Time per craft, used together with `crafting_speed` in place of `speed` if `use_duration` is set.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub duration: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub crafting_speed: String,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub use_duration: bool,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Amount of each input given back per craft, as by a catalyst, so that only the net change is wired. Missing ones are none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_returns: Vec<String>,
+    /// Weights splitting the flow of each pin among the resources it's wired to, by resource node.
+    /// Unlisted resources weigh 1.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub input_weights: Vec<BTreeMap<NodeId, String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output_weights: Vec<BTreeMap<NodeId, String>>,
+    /// Chance of each output that varies, by pin. Missing ones are certain.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output_chances: Vec<Chance>,
+    #[serde(default, skip_serializing_if = "Not::not")]
+    pub locked: bool,
+    /// Power drawn by each running machine, and by each idle machine of the capacity. Empty for none.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub power: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub idle_power: String,
+    /// Modules in the machine's slots, and beacons around it with the effects each one transmits.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modules: Vec<Module>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub beacons: Vec<Module>,
+}
+
+impl NodeMeta {
+    pub fn label(&self) -> &str {
+        match self {
+            NodeMeta::Resource(meta) => &meta.label,
+            NodeMeta::Process(meta) => &meta.label,
+            NodeMeta::Subfactory(meta) => &meta.label,
+        }
+    }
+}
+
+/// Chart nested in a node, which works like a process running `scale` times the child chart.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SubfactoryMeta {
+    pub label: String,
+    pub scale: String,
+    pub chart: Snarl<NodeMeta>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+    /// Boundary the node's wires in the parent chart were made for, to keep them on the same child resources when it changes.
+    /// `None` until first synced, e.g. in charts saved before it was recorded. Parsed documents are synced right away.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pins: Option<[Vec<NodeId>; 2]>,
+}
+
+impl SubfactoryMeta {
+    /// Resources of the child chart exposed as the node's inputs and outputs, in node order: the ones only consumed are inputs,
+    /// and the ones only produced, or produced and consumed with a base rate, are outputs.
+    pub fn boundary(&self) -> [Vec<NodeId>; 2] {
+        let (mut produced, mut consumed) = (HashSet::new(), HashSet::new());
+        for (from, to) in self.chart.wires() {
+            if let NodeMeta::Resource(_) = &self.chart[from.node] {
+                consumed.insert(from.node)
+            } else {
+                produced.insert(to.node)
+            };
+        }
+        let (mut inputs, mut outputs) = (Vec::new(), Vec::new());
+        for (node, meta) in self.chart.node_ids() {
+            let NodeMeta::Resource(meta) = meta else { continue };
+            match (produced.contains(&node), consumed.contains(&node)) {
+                (false, true) => inputs.push(node),
+                (true, false) => outputs.push(node),
+                (true, true) if meta.use_base_rate => outputs.push(node),
+                _ => (),
+            }
+        }
+        inputs.sort();
+        outputs.sort();
+        [inputs, outputs]
+    }
+}
+
+/// Move the wires of each subfactory in the chart, and in the ones nested in it, to the pins of the same child resources
+/// after their boundaries changed, dropping those of resources no longer on it.
+pub fn sync_boundaries(chart: &mut Snarl<NodeMeta>) {
+    let mut moves = HashMap::new();
+    for (node, meta) in chart.nodes_ids_mut() {
+        let NodeMeta::Subfactory(meta) = meta else { continue };
+        sync_boundaries(&mut meta.chart);
+        let boundary = meta.boundary();
+        let Some(pins) = meta.pins.replace(boundary.clone()) else { continue };
+        let false = pins == boundary else { continue };
+        moves.insert(node, [0, 1].map(|side| Vec::from_iter(pins[side].iter().map(|r| boundary[side].iter().position(|x| x == r)))));
+    }
+    let moved = |node: NodeId, pin: usize, side: usize| match moves.get(&node) {
+        Some(x) => x[side].get(pin).copied().flatten(),
+        None => Some(pin),
+    };
+    let wires = Vec::from_iter(chart.wires().filter(|(from, to)| moves.contains_key(&from.node) || moves.contains_key(&to.node)));
+    wires.iter().for_each(|&(from, to)| _ = chart.disconnect(from, to));
+    for (from, to) in wires {
+        let (Some(output), Some(input)) = (moved(from.node, from.output, 1), moved(to.node, to.input, 0)) else { continue };
+        _ = chart.connect(OutPinId { node: from.node, output }, InPinId { node: to.node, input });
+    }
+}
+
+/// Output yielded with a probability, in an amount spread evenly from the pin's quantity to `max`. Empty for certain and fixed.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Chance {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub probability: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub max: String,
+}
+
+/// Effects of a kind of module or beacon, as fractions added to the base, e.g. `0.5` for +50%.
+/// Productivity only scales the outputs, and power only the power drawn while running. Empty for none.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Module {
+    /// Number of these modules or beacons. Empty for one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub count: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub speed: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub productivity: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub power: String,
+}
+
+/// Parse a saved document, falling back to the older format with only the chart.
+pub fn parse_document(data: &str) -> Result<Document> {
+    let mut doc = ron::from_str(data).or_else(|e| {
+        ron::from_str(data).map(|chart| Document { chart, variables: Vec::new(), time_unit: None, groups: Vec::new() }).map_err(|_| e)
+    })?;
+    sync_boundaries(&mut doc.chart);
+    Ok(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{feed, process, resource};
+    use emath::Pos2;
+
+    #[test]
+    fn boundary_changes() {
+        let mut child = Snarl::new();
+        let (coal, ore) = (resource(&mut child, "Coal"), resource(&mut child, "Ore"));
+        let smelter = process(&mut child, "Smelter", "1", "1", &["1", "1"], &[]);
+        feed(&mut child, coal, smelter, 0);
+        feed(&mut child, ore, smelter, 1);
+        let mut chart = Snarl::new();
+        let (outer_coal, outer_ore) = (resource(&mut chart, "Coal"), resource(&mut chart, "Ore"));
+        let meta = SubfactoryMeta { label: "Smelting".to_owned(), scale: "1".to_owned(), chart: child, groups: Vec::new(), pins: None };
+        let node = chart.insert_node(Pos2::ZERO, NodeMeta::Subfactory(Box::new(meta)));
+        feed(&mut chart, outer_coal, node, 0);
+        feed(&mut chart, outer_ore, node, 1);
+        let doc = Document { chart, variables: Vec::new(), time_unit: None, groups: Vec::new() };
+        let mut chart = parse_document(&ron::to_string(&doc).unwrap()).unwrap().chart;
+        let data = ron::to_string(&chart).unwrap();
+        sync_boundaries(&mut chart);
+        assert_eq!(ron::to_string(&chart).unwrap(), data);
+        let NodeMeta::Subfactory(meta) = &mut chart[node] else { unreachable!() };
+        assert_eq!(meta.pins, Some([vec![coal, ore], vec![]]));
+        meta.chart.drop_inputs(InPinId { node: smelter, input: 0 });
+        sync_boundaries(&mut chart);
+        let wires = Vec::from_iter(chart.wires().map(|(from, to)| (from.node, to.input)));
+        assert_eq!(wires, [(outer_ore, 0)]);
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+
+#[cfg(target_arch = "wasm32")]
+pub use web::{pick_file, read_clipboard, spawn};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::{pick_file, read_clipboard, spawn};
+
+/// Decode a text file as UTF-8, or UTF-16 if it starts with a byte order mark.
+fn decode_text(data: Vec<u8>) -> Result<String> {
+    if let Some(data) = data.strip_prefix(&[0xFF, 0xFE]) {
+        let units = data.chunks_exact(2).map(|x| u16::from_le_bytes([x[0], x[1]]));
+        return Ok(String::from_utf16(&Vec::from_iter(units))?);
+    }
+    Ok(String::from_utf8(data)?)
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use anyhow::{Context as _, Result, anyhow};
+    use js_sys::{Promise, Uint8Array};
+    use wasm_bindgen::prelude::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{HtmlInputElement, window};
+
+    pub use wasm_bindgen_futures::spawn_local as spawn;
+
+    pub async fn read_clipboard() -> Result<String> {
+        let data = JsFuture::from(window().unwrap().navigator().clipboard().read_text()).await;
+        data.map_err(|e| anyhow!("{e:?}")).and_then(|x| x.as_string().context("Not a string"))
+    }
+
+    /// Let the user pick a JSON file. Return `None` if cancelled.
+    pub async fn pick_file() -> Result<Option<String>> {
+        let input = window().unwrap().document().unwrap().create_element("input").map_err(|e| anyhow!("{e:?}"))?;
+        let input = input.unchecked_into::<HtmlInputElement>();
+        input.set_type("file");
+        input.set_accept(".json");
+        let picked = Promise::new(&mut |resolve, _| {
+            input.set_onchange(Some(&resolve));
+            _ = input.add_event_listener_with_callback("cancel", &resolve);
+        });
+        input.click();
+        JsFuture::from(picked).await.map_err(|e| anyhow!("{e:?}"))?;
+        let Some(file) = input.files().and_then(|x| x.get(0)) else { return Ok(None) };
+        let data = Uint8Array::new(&JsFuture::from(file.array_buffer()).await.map_err(|e| anyhow!("{e:?}"))?).to_vec();
+        super::decode_text(data).map(Some)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use anyhow::Result;
+    use rfd::FileDialog;
+    use std::{fs, future::Future};
+
+    /// Everything is synchronous on native, so just run it to completion.
+    pub fn spawn(future: impl Future<Output = ()> + 'static) { pollster::block_on(future) }
+
+    pub async fn read_clipboard() -> Result<String> { Ok(arboard::Clipboard::new()?.get_text()?) }
+
+    /// Let the user pick a JSON file. Return `None` if cancelled.
+    pub async fn pick_file() -> Result<Option<String>> {
+        let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() else { return Ok(None) };
+        super::decode_text(fs::read(path)?).map(Some)
+    }
+}
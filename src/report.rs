@@ -0,0 +1,145 @@
+use crate::THRESHOLD;
+use crate::balance::label;
+use crate::format::{RateFormat, format_float};
+use crate::model::NodeMeta;
+use crate::stats::ChartStats;
+use egui_snarl::{NodeId, Snarl};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+
+/// Role of a resource, from how it's wired to processes.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Role {
+    /// Only consumed.
+    Raw,
+    /// Only produced.
+    Product,
+    /// Only produced, and only by processes that also produce something consumed.
+    Byproduct,
+    /// Both produced and consumed.
+    Intermediate,
+    /// Not wired to any process.
+    Unused,
+}
+
+pub fn classify(chart: &Snarl<NodeMeta>) -> HashMap<NodeId, Role> {
+    let (mut produced, mut consumed) = (HashSet::new(), HashSet::new());
+    let mut producers = HashMap::<NodeId, Vec<NodeId>>::new();
+    let mut outputs = HashMap::<NodeId, Vec<NodeId>>::new();
+    for (from, to) in chart.wires() {
+        match (&chart[from.node], &chart[to.node]) {
+            (NodeMeta::Process(_) | NodeMeta::Subfactory(_), NodeMeta::Resource(_)) => {
+                produced.insert(to.node);
+                producers.entry(to.node).or_default().push(from.node);
+                outputs.entry(from.node).or_default().push(to.node);
+            }
+            (NodeMeta::Resource(_), NodeMeta::Process(_) | NodeMeta::Subfactory(_)) => drop(consumed.insert(from.node)),
+            _ => (),
+        }
+    }
+    let resources = chart.node_ids().filter(|x| matches!(x.1, NodeMeta::Resource(_)));
+    HashMap::from_iter(resources.map(|(node, _)| {
+        let role = match (produced.contains(&node), consumed.contains(&node)) {
+            (false, false) => Role::Unused,
+            (false, true) => Role::Raw,
+            (true, true) => Role::Intermediate,
+            (true, false) => {
+                let by = producers[&node].iter().all(|p| outputs[p].iter().any(|x| consumed.contains(x)));
+                if by { Role::Byproduct } else { Role::Product }
+            }
+        };
+        (node, role)
+    }))
+}
+
+/// List the draw of raw resources, the output of products and byproducts and the net rate of intermediates, with totals,
+/// followed by the power drawn by processes of each label.
+pub fn totals_report(chart: &Snarl<NodeMeta>, stats: &ChartStats, fmt: RateFormat) -> String {
+    let roles = classify(chart);
+    let mut report = String::new();
+    let sections = [
+        (Role::Raw, "Raw inputs (consumption)"),
+        (Role::Product, "Final products (production)"),
+        (Role::Byproduct, "Byproducts (production)"),
+        (Role::Intermediate, "Intermediates (net)"),
+    ];
+    for (role, title) in sections {
+        let mut nodes = Vec::from_iter(roles.iter().filter(|x| *x.1 == role).map(|x| *x.0));
+        let false = nodes.is_empty() else { continue };
+        nodes.sort_by(|&a, &b| label(chart, a).cmp(label(chart, b)));
+        (!report.is_empty()).then(|| report += "\n\n");
+        report += title;
+        let mut total = 0.;
+        for node in nodes {
+            let r = stats.resource(node);
+            let rate = match role {
+                Role::Raw => r.dec,
+                Role::Product | Role::Byproduct => r.inc,
+                _ => r.net,
+            };
+            let rate = if r.invalid { "invalid".to_owned() } else { (total += rate, fmt.format(rate)).1 };
+            _ = write!(report, "\n    {}: {rate}", label(chart, node));
+            let NodeMeta::Resource(meta) = &chart[node] else { unreachable!() };
+            meta.use_base_rate.then(|| _ = write!(report, " (base rate {})", fmt.format(r.base_rate)));
+        }
+        (role != Role::Intermediate).then(|| _ = write!(report, "\n    Total: {}", fmt.format(total)));
+    }
+    let mut power = BTreeMap::<&str, (usize, f64)>::new();
+    for (node, _) in chart.node_ids().filter(|x| !matches!(x.1, NodeMeta::Resource(_))) {
+        let stats = stats.process(node);
+        let false = stats.power == 0. else { continue };
+        let entry = power.entry(label(chart, node)).or_default();
+        entry.0 += 1;
+        entry.1 += stats.power;
+    }
+    if !power.is_empty() {
+        (!report.is_empty()).then(|| report += "\n\n");
+        report += "Power by process";
+        power.iter().for_each(|(label, (n, power))| _ = write!(report, "\n    {label} (×{n}): {}", format_float(*power, THRESHOLD)));
+        _ = write!(report, "\n    Total: {}", format_float(stats.power, THRESHOLD));
+    }
+    if report.is_empty() { "There are no resources wired to processes.".to_owned() } else { report }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Env;
+    use crate::fixtures::{emit, feed, process, resource};
+    use crate::format::TimeUnit;
+
+    /// Ore -> Plate + Slag, Plate + Coal -> Gear.
+    fn chart() -> (Snarl<NodeMeta>, [NodeId; 6]) {
+        let mut chart = Snarl::new();
+        let [ore, coal, plate, slag, gear, unused] = ["Ore", "Coal", "Plate", "Slag", "Gear", "Unused"].map(|x| resource(&mut chart, x));
+        let smelter = process(&mut chart, "Smelter", "1", "1", &["2"], &["2", "1"]);
+        feed(&mut chart, ore, smelter, 0);
+        emit(&mut chart, smelter, 0, plate);
+        emit(&mut chart, smelter, 1, slag);
+        let assembler = process(&mut chart, "Assembler", "1", "1", &["2", "1"], &["1"]);
+        feed(&mut chart, plate, assembler, 0);
+        feed(&mut chart, coal, assembler, 1);
+        emit(&mut chart, assembler, 0, gear);
+        (chart, [ore, coal, plate, slag, gear, unused])
+    }
+
+    #[test]
+    fn classify_roles() {
+        let (chart, [ore, coal, plate, slag, gear, unused]) = chart();
+        let roles = classify(&chart);
+        assert_eq!(
+            [ore, coal, plate, slag, gear, unused].map(|x| roles[&x]),
+            [Role::Raw, Role::Raw, Role::Intermediate, Role::Byproduct, Role::Product, Role::Unused]
+        );
+    }
+
+    #[test]
+    fn report_totals() {
+        let (chart, _) = chart();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        let report = totals_report(&chart, &stats, RateFormat::new(Some(TimeUnit::Second), TimeUnit::Second));
+        assert!(report.starts_with("Raw inputs (consumption)\n    Coal: 1 /s\n    Ore: 2 /s\n    Total: 3 /s\n\n"), "{report}");
+        assert!(report.contains("Final products (production)\n    Gear: 1 /s\n    Total: 1 /s"), "{report}");
+        assert!(report.ends_with("Intermediates (net)\n    Plate: 0 /s"), "{report}");
+    }
+}
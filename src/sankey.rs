@@ -0,0 +1,101 @@
+use crate::model::NodeMeta;
+use crate::stats::ChartStats;
+use egui_snarl::{NodeId, Snarl};
+use std::collections::{HashMap, HashSet};
+
+/// Chart laid out as a Sankey diagram: nodes in columns by depth, linked by bands as wide as their rates.
+pub struct Sankey {
+    /// Nodes of each column, from the sources to the sinks.
+    pub columns: Vec<Vec<NodeId>>,
+    pub links: Vec<Link>,
+}
+
+pub struct Link {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub rate: f64,
+}
+
+impl Sankey {
+    /// Each node goes one column after the deepest node feeding it, ignoring wires that close a cycle.
+    /// Within a column, nodes are ordered by the average position of the nodes feeding them.
+    /// Wires with an invalid rate are left out.
+    pub fn layout(chart: &Snarl<NodeMeta>, stats: &ChartStats) -> Self {
+        let mut links = Vec::from_iter(
+            chart.wires().filter_map(|(from, to)| Some(Link { from: from.node, to: to.node, rate: stats.wire_rate(chart, from, to)? })),
+        );
+        links.sort_by_key(|x| (x.from, x.to));
+        let mut succ = HashMap::<NodeId, Vec<NodeId>>::new();
+        links.iter().for_each(|x| succ.entry(x.from).or_default().push(x.to));
+        let mut nodes = Vec::from_iter(chart.node_ids().map(|x| x.0));
+        nodes.sort();
+
+        // Depth-first search for a topological order. Back edges are the ones going backward in it.
+        let (mut visited, mut order) = (HashSet::new(), Vec::new());
+        fn visit(node: NodeId, succ: &HashMap<NodeId, Vec<NodeId>>, visited: &mut HashSet<NodeId>, order: &mut Vec<NodeId>) {
+            let true = visited.insert(node) else { return };
+            succ.get(&node).into_iter().flatten().for_each(|&x| visit(x, succ, visited, order));
+            order.push(node);
+        }
+        nodes.iter().for_each(|&x| visit(x, &succ, &mut visited, &mut order));
+        let mut depth = HashMap::<NodeId, usize>::new();
+        let rank = HashMap::<NodeId, usize>::from_iter(order.iter().rev().enumerate().map(|(i, &x)| (x, i)));
+        for &node in order.iter().rev() {
+            let d = *depth.entry(node).or_default();
+            for &x in succ.get(&node).into_iter().flatten().filter(|x| rank[x] > rank[&node]) {
+                let x = depth.entry(x).or_default();
+                *x = (*x).max(d + 1);
+            }
+        }
+
+        let mut columns = Vec::<Vec<NodeId>>::new();
+        let mut pos = HashMap::<NodeId, f64>::new();
+        let mut pred = HashMap::<NodeId, Vec<NodeId>>::new();
+        links.iter().for_each(|x| pred.entry(x.to).or_default().push(x.from));
+        for node in nodes {
+            let d = depth[&node];
+            columns.resize_with(columns.len().max(d + 1), Vec::new);
+            columns[d].push(node);
+        }
+        for column in &mut columns {
+            let key = |node: &NodeId| {
+                let placed = Vec::from_iter(pred.get(node).into_iter().flatten().filter_map(|x| pos.get(x)));
+                if placed.is_empty() { f64::INFINITY } else { placed.iter().copied().sum::<f64>() / placed.len() as f64 }
+            };
+            let mut keyed = Vec::from_iter(column.iter().map(|x| (key(x), *x)));
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            *column = Vec::from_iter(keyed.into_iter().map(|x| x.1));
+            pos.extend(column.iter().enumerate().map(|(i, &x)| (x, i as f64)));
+        }
+        Self { columns, links }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Env;
+    use crate::fixtures::{emit, feed, process, resource};
+
+    /// Makes one `output` from two `input`.
+    fn link(chart: &mut Snarl<NodeMeta>, input: NodeId, output: NodeId) -> NodeId {
+        let node = process(chart, "", "1", "1", &["2"], &["1"]);
+        feed(chart, input, node, 0);
+        emit(chart, node, 0, output);
+        node
+    }
+
+    #[test]
+    fn columns_by_depth() {
+        let mut chart = Snarl::new();
+        let (ore, plate, gear) = (resource(&mut chart, "Ore"), resource(&mut chart, "Plate"), resource(&mut chart, "Gear"));
+        let smelter = link(&mut chart, ore, plate);
+        let assembler = link(&mut chart, plate, gear);
+        // Recycles gears back into plates, closing a cycle.
+        let recycler = link(&mut chart, gear, plate);
+        let sankey = Sankey::layout(&chart, &ChartStats::compute(&chart, &Env::new(&[])));
+        assert_eq!(sankey.columns, [vec![ore], vec![smelter], vec![plate], vec![assembler], vec![gear], vec![recycler]]);
+        assert_eq!(sankey.links.len(), 6);
+        assert!(sankey.links.iter().all(|x| x.rate == if matches!(chart[x.to], NodeMeta::Process(_)) { 2. } else { 1. }));
+    }
+}
@@ -0,0 +1,258 @@
+const EPSILON: f64 = 1E-9;
+
+pub struct LinearSolution {
+    pub values: Vec<f64>,
+    /// Columns that weren't determined by the equations and were kept at their initial values.
+    pub free: Vec<usize>,
+    /// Rows that contradict the others.
+    pub inconsistent: Vec<usize>,
+}
+
+/// Solve `rows * x = rhs` by Gauss-Jordan elimination.
+/// Free columns take their values from `initial`; inconsistent rows are reported and otherwise ignored.
+pub fn solve_linear(mut rows: Vec<Vec<f64>>, mut rhs: Vec<f64>, initial: &[f64]) -> LinearSolution {
+    let n = initial.len();
+    let mut order = Vec::from_iter(0..rows.len());
+    let mut pivots = Vec::new();
+    for col in 0..n {
+        let rank = pivots.len();
+        let Some(best) = (rank..rows.len()).max_by(|&a, &b| rows[a][col].abs().total_cmp(&rows[b][col].abs())) else { break };
+        let true = rows[best][col].abs() > EPSILON else { continue };
+        rows.swap(rank, best);
+        rhs.swap(rank, best);
+        order.swap(rank, best);
+        let div = rows[rank][col];
+        rows[rank].iter_mut().for_each(|x| *x /= div);
+        rhs[rank] /= div;
+        let pivot = rows[rank].clone();
+        for i in 0..rows.len() {
+            let false = i == rank else { continue };
+            let factor = rows[i][col];
+            let true = factor != 0. else { continue };
+            rows[i][col..].iter_mut().zip(&pivot[col..]).for_each(|(x, y)| *x -= factor * y);
+            rhs[i] -= factor * rhs[rank];
+        }
+        pivots.push(col);
+    }
+    let mut values = initial.to_owned();
+    let free = Vec::from_iter((0..n).filter(|x| !pivots.contains(x)));
+    for (row, &col) in pivots.iter().enumerate() {
+        values[col] = rhs[row] - free.iter().map(|&j| rows[row][j] * initial[j]).sum::<f64>();
+    }
+    let inconsistent = Vec::from_iter((pivots.len()..rows.len()).filter(|&i| rhs[i].abs() > EPSILON).map(|i| order[i]));
+    LinearSolution { values, free, inconsistent }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Relation {
+    Le,
+    Ge,
+    Eq,
+}
+
+pub struct Constraint {
+    pub coefs: Vec<f64>,
+    pub relation: Relation,
+    pub rhs: f64,
+}
+
+pub enum LpResult {
+    Optimal(Vec<f64>),
+    Infeasible,
+    Unbounded,
+}
+
+fn pivot(tab: &mut [Vec<f64>], cost: &mut [f64], basis: &mut [usize], row: usize, col: usize) {
+    let div = tab[row][col];
+    tab[row].iter_mut().for_each(|x| *x /= div);
+    let pivot = tab[row].clone();
+    for (i, r) in tab.iter_mut().enumerate() {
+        let false = i == row else { continue };
+        let factor = r[col];
+        let true = factor != 0. else { continue };
+        r.iter_mut().zip(&pivot).for_each(|(x, y)| *x -= factor * y);
+    }
+    let factor = cost[col];
+    cost.iter_mut().zip(&pivot).for_each(|(x, y)| *x -= factor * y);
+    basis[row] = col;
+}
+
+/// Run the simplex method with Bland's rule, only letting the first `allowed` columns enter the basis.
+/// Return false if unbounded.
+fn run_simplex(tab: &mut [Vec<f64>], cost: &mut [f64], basis: &mut [usize], allowed: usize) -> bool {
+    let rhs = cost.len() - 1;
+    loop {
+        let Some(col) = (0..allowed).find(|&j| cost[j] > EPSILON) else { return true };
+        let mut best = None::<(usize, f64)>;
+        for (i, r) in tab.iter().enumerate() {
+            let true = r[col] > EPSILON else { continue };
+            let ratio = r[rhs] / r[col];
+            if best.is_none_or(|(b, x)| ratio < x - EPSILON || (ratio < x + EPSILON && basis[i] < basis[b])) {
+                best = Some((i, ratio));
+            }
+        }
+        let Some((row, _)) = best else { return false };
+        pivot(tab, cost, basis, row, col);
+    }
+}
+
+/// Maximize `objective · x` subject to `constraints` and `x >= 0` using the two-phase simplex method.
+pub fn maximize(objective: &[f64], constraints: &[Constraint]) -> LpResult {
+    let n = objective.len();
+    let n_slack = constraints.iter().filter(|x| x.relation != Relation::Eq).count();
+    let needs_art = |x: &Constraint| match x.relation {
+        Relation::Le => x.rhs < 0.,
+        Relation::Ge => x.rhs >= 0.,
+        Relation::Eq => true,
+    };
+    let n_art = constraints.iter().filter(|x| needs_art(x)).count();
+    let width = n + n_slack + n_art + 1;
+    let (mut slack, mut art) = (n, n + n_slack);
+    let mut tab = Vec::new();
+    let mut basis = Vec::new();
+    for constraint in constraints {
+        let mut row = vec![0.; width];
+        let sign = if constraint.rhs < 0. { -1. } else { 1. };
+        row[..n].iter_mut().zip(&constraint.coefs).for_each(|(x, y)| *x = sign * y);
+        row[width - 1] = sign * constraint.rhs;
+        if constraint.relation != Relation::Eq {
+            row[slack] = if constraint.relation == Relation::Le { sign } else { -sign };
+            slack += 1;
+        }
+        if needs_art(constraint) {
+            row[art] = 1.;
+            basis.push(art);
+            art += 1;
+        } else {
+            basis.push(slack - 1);
+        }
+        tab.push(row);
+    }
+    let mut cost = vec![0.; width];
+    for (row, &col) in tab.iter().zip(&basis) {
+        let true = col >= n + n_slack else { continue };
+        cost.iter_mut().zip(row).for_each(|(x, y)| *x += y);
+        cost[col] = 0.;
+    }
+    run_simplex(&mut tab, &mut cost, &mut basis, width - 1);
+    let true = cost[width - 1] < EPSILON else { return LpResult::Infeasible };
+    for row in 0..tab.len() {
+        let true = basis[row] >= n + n_slack else { continue };
+        let Some(col) = (0..n + n_slack).find(|&j| tab[row][j].abs() > EPSILON) else { continue };
+        pivot(&mut tab, &mut cost, &mut basis, row, col);
+    }
+    cost.fill(0.);
+    cost[..n].copy_from_slice(objective);
+    for (row, &col) in tab.iter().zip(&basis) {
+        let factor = cost[col];
+        let true = factor != 0. else { continue };
+        cost.iter_mut().zip(row).for_each(|(x, y)| *x -= factor * y);
+    }
+    if !run_simplex(&mut tab, &mut cost, &mut basis, n + n_slack) {
+        return LpResult::Unbounded;
+    }
+    let mut values = vec![0.; n];
+    for (row, &col) in tab.iter().zip(&basis) {
+        if col < n {
+            values[col] = row[width - 1];
+        }
+    }
+    LpResult::Optimal(values)
+}
+
+/// Like [`maximize`], but with the columns flagged in `integer` restricted to integers, using branch and bound.
+/// The search gives up after solving `limit` relaxations; the second return value tells whether it finished.
+pub fn maximize_integer(objective: &[f64], constraints: &[Constraint], integer: &[bool], limit: usize) -> (LpResult, bool) {
+    let n = objective.len();
+    let mut best = None::<(f64, Vec<f64>)>;
+    let mut stack = vec![Vec::<(usize, Relation, f64)>::new()];
+    let mut solved = 0;
+    while let Some(bounds) = stack.pop() {
+        if solved == limit {
+            return (best.map_or(LpResult::Infeasible, |x| LpResult::Optimal(x.1)), false);
+        }
+        solved += 1;
+        let mut constraints = Vec::from_iter(constraints.iter().map(|x| Constraint { coefs: x.coefs.clone(), ..*x }));
+        for &(col, relation, rhs) in &bounds {
+            let mut coefs = vec![0.; n];
+            coefs[col] = 1.;
+            constraints.push(Constraint { coefs, relation, rhs });
+        }
+        let values = match maximize(objective, &constraints) {
+            LpResult::Optimal(x) => x,
+            LpResult::Infeasible => continue,
+            LpResult::Unbounded => return (LpResult::Unbounded, true),
+        };
+        let value = objective.iter().zip(&values).map(|(a, b)| a * b).sum::<f64>();
+        if best.as_ref().is_some_and(|x| value < x.0 + EPSILON) {
+            continue;
+        }
+        let Some(col) = (0..n).find(|&j| integer[j] && (values[j] - values[j].round()).abs() > 1E-6) else {
+            best = Some((value, values));
+            continue;
+        };
+        for (relation, rhs) in [(Relation::Le, values[col].floor()), (Relation::Ge, values[col].ceil())] {
+            let mut bounds = bounds.clone();
+            bounds.push((col, relation, rhs));
+            stack.push(bounds);
+        }
+    }
+    (best.map_or(LpResult::Infeasible, |x| LpResult::Optimal(x.1)), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn solve_linear_unique() {
+        let solution = solve_linear(vec![vec![1., 1.], vec![1., -1.]], vec![3., 1.], &[0., 0.]);
+        assert_eq!((solution.values, solution.free, solution.inconsistent), (vec![2., 1.], vec![], vec![]));
+    }
+
+    #[test]
+    fn solve_linear_free_columns() {
+        let solution = solve_linear(vec![vec![1., -2., 0.]], vec![0.], &[5., 1.5, 4.]);
+        assert_eq!((solution.values, solution.free, solution.inconsistent), (vec![3., 1.5, 4.], vec![1, 2], vec![]));
+    }
+
+    #[test]
+    fn solve_linear_inconsistent_rows() {
+        let solution = solve_linear(vec![vec![0., 0.], vec![1., 0.], vec![2., 0.]], vec![0., 1., 3.], &[0., 7.]);
+        assert_eq!((solution.values, solution.free, solution.inconsistent), (vec![1.5, 7.], vec![1], vec![1]));
+    }
+
+    fn le(coefs: &[f64], rhs: f64) -> Constraint { Constraint { coefs: coefs.to_owned(), relation: Relation::Le, rhs } }
+    fn ge(coefs: &[f64], rhs: f64) -> Constraint { Constraint { coefs: coefs.to_owned(), relation: Relation::Ge, rhs } }
+
+    fn optimal(result: LpResult) -> Vec<f64> {
+        let LpResult::Optimal(values) = result else { panic!("not optimal") };
+        values
+    }
+
+    fn assert_close(values: &[f64], expected: &[f64]) {
+        assert!(values.len() == expected.len() && values.iter().zip(expected).all(|(x, y)| (x - y).abs() < 1E-9), "{values:?} != {expected:?}");
+    }
+
+    #[test]
+    fn maximize_optimal() {
+        let constraints = [le(&[1., 1.], 4.), le(&[1., 3.], 6.), ge(&[1., 0.], 1.)];
+        assert_close(&optimal(maximize(&[3., 2.], &constraints)), &[4., 0.]);
+        let constraints = [Constraint { coefs: vec![1., 1.], relation: Relation::Eq, rhs: 2. }, le(&[-1., 0.], -0.5)];
+        assert_close(&optimal(maximize(&[-1., 1.], &constraints)), &[0.5, 1.5]);
+    }
+
+    #[test]
+    fn maximize_infeasible_and_unbounded() {
+        assert!(matches!(maximize(&[1.], &[le(&[1.], 1.), ge(&[1.], 2.)]), LpResult::Infeasible));
+        assert!(matches!(maximize(&[0., 0.], &[le(&[1., 1.], -1.)]), LpResult::Infeasible));
+        assert!(matches!(maximize(&[1., 0.], &[le(&[1., -1.], 1.)]), LpResult::Unbounded));
+    }
+
+    /// Beale's example, which cycles forever under the largest-coefficient rule.
+    #[test]
+    fn maximize_degenerate() {
+        let constraints = [le(&[0.25, -8., -1., 9.], 0.), le(&[0.5, -12., -0.5, 3.], 0.), le(&[0., 0., 1., 0.], 1.)];
+        assert_close(&optimal(maximize(&[0.75, -20., 0.5, -6.], &constraints)), &[1., 0., 1., 0.]);
+    }
+}
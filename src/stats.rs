@@ -0,0 +1,792 @@
+use crate::THRESHOLD;
+use crate::eval::{self, Env, Ref, RefFunc};
+use crate::model::{Chance, NodeMeta, ProcessMeta, SubfactoryMeta};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use std::cell::{LazyCell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+
+pub struct ChartStats {
+    pub nodes: HashMap<NodeId, NodeStats>,
+    /// Total power drawn by all valid processes.
+    pub power: f64,
+}
+
+pub enum NodeStats {
+    Resource(ResourceStats),
+    Process(ProcessStats),
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct ResourceStats {
+    pub invalid: bool,
+    pub base_rate: f64,
+    pub inc: f64,
+    pub dec: f64,
+    pub net: f64,
+    /// Change in net rate if every process ran its whole number of machines at full activity.
+    pub overproduction: f64,
+}
+
+pub struct ProcessStats {
+    pub status: ProcessStatus,
+    pub machines: f64,
+    pub values: Rc<ProcessValues>,
+    pub input_rates: Box<[f64]>,
+    pub output_rates: Box<[f64]>,
+    pub power: f64,
+}
+
+pub enum ProcessStatus {
+    Invalid,
+    Balanced,
+    Deficient,
+    Excess,
+}
+
+/// Evaluated fields of a process. An empty activity is taken as equal to the capacity.
+pub struct ProcessValues {
+    pub capacity: Option<f64>,
+    pub activity: Option<f64>,
+    pub speed: Option<f64>,
+    /// Net quantity of each input after what's given back, negative if more is given back than consumed.
+    pub inputs: Box<[Option<f64>]>,
+    /// Expected quantity of each output.
+    pub outputs: Box<[Option<f64>]>,
+    pub output_yields: Box<[Option<Yield>]>,
+    /// Fraction of each pin's flow going to each wired resource; `None` if unwired or a weight is invalid.
+    pub input_splits: Box<[Option<Split>]>,
+    pub output_splits: Box<[Option<Split>]>,
+    /// Power per running and per idle machine.
+    pub power: Option<f64>,
+    pub idle_power: Option<f64>,
+    /// Already applied to `speed`, `outputs` and `power`.
+    pub effects: Option<Effects>,
+}
+
+/// Factors by which modules and beacons scale the speed, the outputs and the power drawn while running.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Effects {
+    pub speed: f64,
+    pub productivity: f64,
+    pub power: f64,
+}
+
+impl Effects {
+    pub const NONE: Self = Self { speed: 1., productivity: 1., power: 1. };
+}
+
+pub type Split = Box<[(NodeId, f64)]>;
+
+/// Probability of an output and the range of its amount per craft, before productivity.
+#[derive(Clone, Copy)]
+pub struct Yield {
+    pub probability: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Yield {
+    pub fn expected(&self) -> f64 { self.probability * (self.min + self.max) / 2. }
+    pub fn is_certain(&self) -> bool { self.probability == 1. && self.min == self.max }
+}
+
+impl Chance {
+    /// `None` if the probability isn't within 0 to 1, or `max` is less than `min`.
+    pub fn evaluate(&self, min: Option<f64>, eval: impl Fn(&str) -> Option<f64>) -> Option<Yield> {
+        let min = min?;
+        let probability = if self.probability.is_empty() { 1. } else { eval(&self.probability)? };
+        let max = if self.max.is_empty() { min } else { eval(&self.max)? };
+        let true = ((0. ..=1.).contains(&probability) && max >= min) else { return None };
+        Some(Yield { probability, min, max })
+    }
+}
+
+fn input_remotes(chart: &Snarl<NodeMeta>, node: NodeId, input: usize) -> Vec<NodeId> {
+    Vec::from_iter(chart.in_pin(InPinId { node, input }).remotes.into_iter().map(|x| x.node))
+}
+
+fn output_remotes(chart: &Snarl<NodeMeta>, node: NodeId, output: usize) -> Vec<NodeId> {
+    Vec::from_iter(chart.out_pin(OutPinId { node, output }).remotes.into_iter().map(|x| x.node))
+}
+
+/// Fraction of a pin's flow going to each resource, by their weights. Unlisted resources weigh 1.
+fn split(remotes: Vec<NodeId>, weights: Option<&BTreeMap<NodeId, String>>, eval: impl Fn(&str) -> Option<f64>) -> Option<Split> {
+    let weights = Option::<Vec<f64>>::from_iter(remotes.iter().map(|r| weights.and_then(|x| x.get(r)).map_or(Some(1.), |x| eval(x))))?;
+    let total = weights.iter().sum::<f64>();
+    let true = (total > 0. && weights.iter().all(|&x| x >= 0.)) else { return None };
+    Some(Box::from_iter(remotes.into_iter().zip(weights).map(|(r, x)| (r, x / total))))
+}
+
+/// Minimum number of whole machines for an activity.
+pub fn machine_count(activity: f64) -> f64 { (activity - THRESHOLD).ceil().max(0.) }
+
+impl ProcessMeta {
+    /// Sum the effects of the modules and beacons. Like in Factorio, speed and power can be cut by at most 80%,
+    /// and productivity can't go below zero.
+    pub fn effects(&self, eval: impl Fn(&str) -> Option<f64>) -> Option<Effects> {
+        let [mut speed, mut productivity, mut power] = [0.; 3];
+        let value = |x: &str| if x.is_empty() { Some(0.) } else { eval(x) };
+        for x in self.modules.iter().chain(&self.beacons) {
+            let count = if x.count.is_empty() { 1. } else { eval(&x.count)? };
+            speed += count * value(&x.speed)?;
+            productivity += count * value(&x.productivity)?;
+            power += count * value(&x.power)?;
+        }
+        Some(Effects { speed: (1. + speed).max(0.2), productivity: 1. + productivity.max(0.), power: (1. + power).max(0.2) })
+    }
+
+    pub fn evaluate(&self, chart: &Snarl<NodeMeta>, node: NodeId, eval: impl Fn(&str) -> Option<f64>) -> ProcessValues {
+        let capacity = eval(&self.capacity);
+        let input_splits = (0..self.inputs.len()).map(|input| split(input_remotes(chart, node, input), self.input_weights.get(input), &eval));
+        let output_splits = (0..self.outputs.len()).map(|output| split(output_remotes(chart, node, output), self.output_weights.get(output), &eval));
+        let effects = self.effects(&eval);
+        let output_yields =
+            self.outputs.iter().enumerate().map(|(i, x)| self.output_chances.get(i).unwrap_or(&<_>::default()).evaluate(eval(x), &eval));
+        let output_yields = Box::from_iter(output_yields);
+        // Productivity only applies to the net gain of a catalyst.
+        let inputs = self.inputs.iter().enumerate().map(|(i, x)| {
+            let returned = self.input_returns.get(i).filter(|x| !x.is_empty()).map_or(Some(0.), |x| eval(x))?;
+            let gain = returned - eval(x)?;
+            Some(-if gain > 0. { gain * effects?.productivity } else { gain })
+        });
+        let speed = if self.use_duration { eval(&self.crafting_speed).zip(eval(&self.duration)).map(|(x, y)| x / y) } else { eval(&self.speed) };
+        let power = if self.power.is_empty() { Some(0.) } else { eval(&self.power) };
+        ProcessValues {
+            capacity,
+            activity: if self.activity.is_empty() { capacity } else { eval(&self.activity) },
+            speed: speed.zip(effects).map(|(x, e)| x * e.speed),
+            inputs: Box::from_iter(inputs),
+            outputs: Box::from_iter(output_yields.iter().map(|x| x.zip(effects).map(|(x, e)| x.expected() * e.productivity))),
+            output_yields,
+            input_splits: Box::from_iter(input_splits),
+            output_splits: Box::from_iter(output_splits),
+            power: power.zip(effects).map(|(x, e)| x * e.power),
+            idle_power: if self.idle_power.is_empty() { Some(0.) } else { eval(&self.idle_power) },
+            effects,
+        }
+    }
+}
+
+impl SubfactoryMeta {
+    /// Evaluate as a process with `scale` as its capacity and activity and a speed of 1, crafting the net flow of each
+    /// boundary resource of the child chart. Power is drawn by the child's processes, unless the child supplies its own.
+    pub fn evaluate(&self, chart: &Snarl<NodeMeta>, node: NodeId, env: &Env, eval: impl Fn(&str) -> Option<f64>) -> ProcessValues {
+        let stats = ChartStats::compute(&self.chart, env);
+        let [inputs, outputs] = self.boundary();
+        let flow = |r: &NodeId| Some(stats.resource(*r)).filter(|x| !x.invalid).map(|x| x.inc - x.dec);
+        let input_splits = Box::from_iter((0..inputs.len()).map(|input| split(input_remotes(chart, node, input), None, &eval)));
+        let output_splits = Box::from_iter((0..outputs.len()).map(|output| split(output_remotes(chart, node, output), None, &eval)));
+        let outputs = Box::from_iter(outputs.iter().map(flow));
+        let supplied = self.chart.nodes().any(|x| matches!(x, NodeMeta::Resource(x) if x.power));
+        let scale = eval(&self.scale);
+        ProcessValues {
+            capacity: scale,
+            activity: scale,
+            speed: Some(1.),
+            inputs: Box::from_iter(inputs.iter().map(|r| flow(r).map(|x| -x))),
+            output_yields: Box::from_iter(outputs.iter().map(|x| x.map(|x| Yield { probability: 1., min: x, max: x }))),
+            outputs,
+            input_splits,
+            output_splits,
+            power: Some(if supplied { 0. } else { stats.power }),
+            idle_power: Some(0.),
+            effects: Some(Effects::NONE),
+        }
+    }
+}
+
+impl ProcessValues {
+    /// Return the minimum whole machines needed and the rate they'd produce running at full activity.
+    pub fn integer_rate(&self) -> Option<(f64, f64)> {
+        let machines = machine_count(self.activity?);
+        Some((machines, machines * self.speed?))
+    }
+
+    pub fn common_rate(&self) -> Option<(f64, ProcessStatus)> {
+        let (capacity, activity) = (self.capacity?, self.activity?);
+        let excess = capacity - activity;
+        let status = if excess < -2. * THRESHOLD {
+            ProcessStatus::Deficient
+        } else if excess > 1. - THRESHOLD {
+            ProcessStatus::Excess
+        } else {
+            ProcessStatus::Balanced
+        };
+        Some((capacity.min(activity) * self.speed?, status))
+    }
+
+    /// Power drawn with `running` machines out of the capacity.
+    pub fn power_at(&self, running: f64) -> Option<f64> { Some(self.power? * running + self.idle_power? * (self.capacity? - running).max(0.)) }
+
+    pub fn power_draw(&self) -> Option<f64> { self.power_at(self.capacity?.min(self.activity?)) }
+
+    /// Quantity per craft of each wired resource, negative for inputs. Pins with an invalid quantity or split are left out.
+    pub fn flows(&self) -> impl Iterator<Item = (NodeId, f64)> {
+        let inputs = self.inputs.iter().zip(&self.input_splits).map(|(qty, split)| (qty.map(|x| -x), split));
+        let outputs = self.outputs.iter().zip(&self.output_splits).map(|(qty, split)| (*qty, split));
+        let pins = inputs.chain(outputs).filter_map(|(qty, split)| Some((qty?, split.as_deref()?)));
+        pins.flat_map(|(qty, split)| split.iter().map(move |&(r, share)| (r, qty * share)))
+    }
+}
+
+fn adjacent(chart: &Snarl<NodeMeta>, node: NodeId) -> BTreeSet<NodeId> {
+    BTreeSet::from_iter(chart.wires().filter_map(|(from, to)| match () {
+        _ if from.node == node => Some(to.node),
+        _ if to.node == node => Some(from.node),
+        _ => None,
+    }))
+}
+
+/// Nodes on a cycle of `deps`, by Tarjan's strongly connected components.
+fn cycles(deps: &HashMap<NodeId, Vec<NodeId>>) -> HashSet<NodeId> {
+    #[derive(Default)]
+    struct Tarjan {
+        lowlinks: HashMap<NodeId, usize>,
+        stack: Vec<NodeId>,
+        cyclic: HashSet<NodeId>,
+    }
+    impl Tarjan {
+        fn visit(&mut self, deps: &HashMap<NodeId, Vec<NodeId>>, node: NodeId) -> usize {
+            let index = self.lowlinks.len();
+            self.lowlinks.insert(node, index);
+            self.stack.push(node);
+            let mut lowlink = index;
+            for &x in &deps[&node] {
+                lowlink = lowlink.min(match self.lowlinks.get(&x) {
+                    None => self.visit(deps, x),
+                    Some(&x_lowlink) if self.stack.contains(&x) => x_lowlink,
+                    Some(_) => continue,
+                });
+            }
+            self.lowlinks.insert(node, lowlink);
+            if lowlink == index {
+                let component = self.stack.split_off(self.stack.iter().rposition(|&x| x == node).unwrap());
+                if component.len() > 1 || deps[&node].contains(&node) {
+                    self.cyclic.extend(component);
+                }
+            }
+            lowlink
+        }
+    }
+    let mut tarjan = Tarjan::default();
+    for &node in deps.keys() {
+        let false = tarjan.lowlinks.contains_key(&node) else { continue };
+        tarjan.visit(deps, node);
+    }
+    tarjan.cyclic
+}
+
+/// Evaluates nodes lazily in dependency order, so that expressions can refer to other nodes. Nodes on a cycle of
+/// references are found up front and are invalid, whichever of them gets evaluated first.
+struct Evaluator<'a> {
+    chart: &'a Snarl<NodeMeta>,
+    env: &'a Env,
+    cyclic: HashSet<NodeId>,
+    processes: RefCell<HashMap<NodeId, Rc<ProcessValues>>>,
+    resources: RefCell<HashMap<NodeId, ResourceStats>>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(chart: &'a Snarl<NodeMeta>, env: &'a Env) -> Self {
+        let mut this = Self { chart, env, cyclic: <_>::default(), processes: <_>::default(), resources: <_>::default() };
+        this.cyclic = cycles(&HashMap::from_iter(chart.node_ids().map(|(node, _)| (node, this.dependencies(node)))));
+        this
+    }
+
+    fn eval(&self, expr: &str) -> Option<f64> { self.env.eval_with_refs(expr, |x| self.resolve(x)).ok() }
+
+    /// The node a reference is to, if its label is unique.
+    fn find(&self, r: &Ref) -> Option<NodeId> {
+        let mut found = self.chart.node_ids().filter(|(_, meta)| match meta {
+            NodeMeta::Resource(meta) => r.func.is_resource() && meta.label == r.label,
+            NodeMeta::Process(meta) => !r.func.is_resource() && meta.label == r.label,
+            NodeMeta::Subfactory(meta) => !r.func.is_resource() && meta.label == r.label,
+        });
+        let (node, _) = found.next()?;
+        found.next().is_none().then_some(node)
+    }
+
+    /// Nodes whose values evaluating `node` may use. Processes are evaluated with every expression being 1 to
+    /// collect all the expressions they'd evaluate.
+    fn dependencies(&self, node: NodeId) -> Vec<NodeId> {
+        let exprs = RefCell::new(Vec::new());
+        let record = |x: &str| {
+            exprs.borrow_mut().push(x.to_owned());
+            Some(1.)
+        };
+        let mut deps = Vec::new();
+        match &self.chart[node] {
+            NodeMeta::Resource(meta) => {
+                meta.use_base_rate.then(|| record(&meta.base_rate));
+                deps.extend(adjacent(self.chart, node));
+                if meta.power {
+                    deps.extend(self.chart.node_ids().filter(|x| !matches!(x.1, NodeMeta::Resource(_))).map(|x| x.0));
+                }
+            }
+            NodeMeta::Process(meta) => drop(meta.evaluate(self.chart, node, record)),
+            NodeMeta::Subfactory(meta) => drop(record(&meta.scale)),
+        }
+        deps.extend(exprs.into_inner().iter().flat_map(|x| eval::refs(x)).filter_map(|x| self.find(&x)));
+        deps
+    }
+
+    fn resolve(&self, r: &Ref) -> Option<f64> {
+        let node = self.find(r)?;
+        match r.func {
+            RefFunc::Net | RefFunc::Inc | RefFunc::Dec => {
+                let stats = self.resource(node);
+                let false = stats.invalid else { return None };
+                Some(match r.func {
+                    RefFunc::Net => stats.net,
+                    RefFunc::Inc => stats.inc,
+                    _ => stats.dec,
+                })
+            }
+            RefFunc::Activity => self.process(node).activity,
+            RefFunc::Capacity => self.process(node).capacity,
+            RefFunc::Rate => Some(self.process(node).common_rate()?.0),
+        }
+    }
+
+    /// Every expression of a process on a cycle is invalid.
+    fn process(&self, node: NodeId) -> Rc<ProcessValues> {
+        if let Some(values) = self.processes.borrow().get(&node) {
+            return values.clone();
+        }
+        let cyclic = self.cyclic.contains(&node);
+        let eval = |x: &str| (!cyclic).then(|| self.eval(x))?;
+        let values = Rc::new(match &self.chart[node] {
+            NodeMeta::Process(meta) => meta.evaluate(self.chart, node, eval),
+            NodeMeta::Subfactory(meta) => meta.evaluate(self.chart, node, self.env, eval),
+            NodeMeta::Resource(_) => unreachable!(),
+        });
+        self.processes.borrow_mut().insert(node, values.clone());
+        values
+    }
+
+    fn resource(&self, node: NodeId) -> ResourceStats {
+        if let Some(stats) = self.resources.borrow().get(&node) {
+            return *stats;
+        }
+        let stats = match self.cyclic.contains(&node) {
+            true => ResourceStats { invalid: true, ..<_>::default() },
+            false => self.evaluate_resource(node),
+        };
+        self.resources.borrow_mut().insert(node, stats);
+        stats
+    }
+
+    fn evaluate_resource(&self, node: NodeId) -> ResourceStats {
+        let NodeMeta::Resource(meta) = &self.chart[node] else { unreachable!() };
+        let mut stats = ResourceStats::default();
+        if meta.use_base_rate {
+            if let Some(base_rate) = self.eval(&meta.base_rate) {
+                stats.base_rate = base_rate;
+                stats.net += base_rate;
+            } else {
+                stats.invalid = true;
+            }
+        }
+        for process in adjacent(self.chart, node) {
+            let values = self.process(process);
+            let (Some((rate, _)), Some((_, full_rate))) = (values.common_rate(), values.integer_rate()) else { continue };
+            for (_, qty) in values.flows().filter(|x| x.0 == node) {
+                if qty > 0. {
+                    stats.inc += rate * qty
+                } else {
+                    stats.dec -= rate * qty
+                }
+                stats.net += rate * qty;
+                stats.overproduction += (full_rate - rate) * qty;
+            }
+        }
+        if meta.power {
+            for (process, meta) in self.chart.node_ids() {
+                let false = matches!(meta, NodeMeta::Resource(_)) else { continue };
+                let values = self.process(process);
+                let (Some(draw), Some((machines, _))) = (values.power_draw(), values.integer_rate()) else { continue };
+                let Some(full_draw) = values.power_at(machines) else { continue };
+                stats.dec += draw;
+                stats.net -= draw;
+                stats.overproduction -= full_draw - draw;
+            }
+        }
+        stats
+    }
+}
+
+impl ChartStats {
+    pub fn compute(chart: &Snarl<NodeMeta>, env: &Env) -> Self {
+        let evaluator = Evaluator::new(chart, env);
+        let mut this = Self { nodes: HashMap::new(), power: 0. };
+        for (node, meta) in chart.node_ids() {
+            let stats = match meta {
+                NodeMeta::Resource(_) => NodeStats::Resource(evaluator.resource(node)),
+                NodeMeta::Process(_) | NodeMeta::Subfactory(_) => {
+                    let values = evaluator.process(node);
+                    let (input_rates, output_rates) = <_>::default();
+                    let mut stats =
+                        ProcessStats { status: ProcessStatus::Invalid, machines: 0., values: values.clone(), input_rates, output_rates, power: 0. };
+                    if let Some((rate, mut status)) = values.common_rate()
+                        && let Some((machines, _)) = values.integer_rate()
+                    {
+                        let mut pin_rate = |qty: &Option<f64>, split: &Option<Split>| {
+                            let (Some(qty), Some(_)) = (qty, split) else { return (0., status = ProcessStatus::Invalid).0 };
+                            rate * qty
+                        };
+                        stats.input_rates = Box::from_iter(values.inputs.iter().zip(&values.input_splits).map(|(x, y)| pin_rate(x, y)));
+                        stats.output_rates = Box::from_iter(values.outputs.iter().zip(&values.output_splits).map(|(x, y)| pin_rate(x, y)));
+                        if let Some(power) = values.power_draw() {
+                            stats.power = power;
+                            this.power += power;
+                        } else {
+                            status = ProcessStatus::Invalid;
+                        }
+                        (stats.status, stats.machines) = (status, machines);
+                    }
+                    NodeStats::Process(stats)
+                }
+            };
+            this.nodes.insert(node, stats);
+        }
+        this
+    }
+
+    pub fn resource(&self, node: NodeId) -> ResourceStats {
+        if let Some(NodeStats::Resource(stats)) = self.nodes.get(&node) { *stats } else { <_>::default() }
+    }
+
+    pub fn process(&self, node: NodeId) -> &ProcessStats {
+        let Some(NodeStats::Process(stats)) = self.nodes.get(&node) else { unreachable!() };
+        stats
+    }
+
+    /// Rate along a wire between a process and a resource. `None` if the process's rate or the pin's split is invalid, or
+    /// the pin is gone, like that of a resource taken off a subfactory's boundary.
+    pub fn wire_rate(&self, chart: &Snarl<NodeMeta>, from: OutPinId, to: InPinId) -> Option<f64> {
+        let (split, rate, r) = match (&chart[from.node], &chart[to.node]) {
+            (NodeMeta::Process(_) | NodeMeta::Subfactory(_), NodeMeta::Resource(_)) => {
+                let stats = self.process(from.node);
+                (stats.values.output_splits.get(from.output)?, stats.output_rates.get(from.output)?, to.node)
+            }
+            (NodeMeta::Resource(_), NodeMeta::Process(_) | NodeMeta::Subfactory(_)) => {
+                let stats = self.process(to.node);
+                (stats.values.input_splits.get(to.input)?, stats.input_rates.get(to.input)?, from.node)
+            }
+            _ => return None,
+        };
+        Some(rate * split.as_ref()?.iter().find(|x| x.0 == r)?.1)
+    }
+
+    /// Net rate at which the processes among `nodes` produce each resource, leaving out the ones they balance among themselves.
+    /// `None` for resources with a wire of invalid rate.
+    pub fn group_flows(&self, chart: &Snarl<NodeMeta>, nodes: &BTreeSet<NodeId>) -> BTreeMap<NodeId, Option<f64>> {
+        let mut flows = BTreeMap::<NodeId, Option<f64>>::new();
+        for (from, to) in chart.wires() {
+            let (r, sign) = match (&chart[from.node], &chart[to.node]) {
+                (NodeMeta::Resource(_), _) if nodes.contains(&to.node) => (from.node, -1.),
+                (_, NodeMeta::Resource(_)) if nodes.contains(&from.node) => (to.node, 1.),
+                _ => continue,
+            };
+            let flow = flows.entry(r).or_insert(Some(0.));
+            *flow = flow.zip(self.wire_rate(chart, from, to)).map(|(x, y)| x + sign * y);
+        }
+        flows.retain(|_, x| x.is_none_or(|x| x.abs() > THRESHOLD));
+        flows
+    }
+}
+
+/// Power drawn by `p` itself is left out too.
+fn resource_rate_excl_process(chart: &Snarl<NodeMeta>, stats: &ChartStats, r: NodeId, p: NodeId) -> f64 {
+    let mut result = stats.resource(r).base_rate;
+    let NodeMeta::Resource(meta) = &chart[r] else { unreachable!() };
+    'outer: for (node, process) in chart.node_ids() {
+        let false = node == p else { continue };
+        let false = matches!(process, NodeMeta::Resource(_)) else { continue };
+        let values = &stats.process(node).values;
+        meta.power.then(|| result -= values.power_draw().unwrap_or(0.));
+        let rate = LazyCell::new(|| values.common_rate().map(|x| x.0));
+        for (_, qty) in values.flows().filter(|x| x.0 == r) {
+            let Some(rate) = *rate else { continue 'outer };
+            result += rate * qty;
+        }
+    }
+    result
+}
+
+/// Activity that balances each resource of a pin's split, given the rate of the whole pin at full activity.
+fn fits<'a>(chart: &'a Snarl<NodeMeta>, stats: &'a ChartStats, node: NodeId, rate: f64, split: &'a Split) -> impl Iterator<Item = f64> + 'a {
+    split.iter().filter(|x| x.1 > 0.).map(move |&(r, share)| resource_rate_excl_process(chart, stats, r, node) / (rate * share))
+}
+
+/// Highest activity at which none of the resources the input draws from runs short.
+pub fn fit_activity_to_input(chart: &Snarl<NodeMeta>, stats: &ChartStats, pin: InPinId) -> Option<f64> {
+    let values = &stats.process(pin.node).values;
+    let (speed, qty, split) = (values.speed?, values.inputs[pin.input]?, values.input_splits[pin.input].as_ref()?);
+    fits(chart, stats, pin.node, speed * qty, split).reduce(f64::min)
+}
+
+/// Lowest activity at which none of the resources the output supplies runs short.
+pub fn fit_activity_to_output(chart: &Snarl<NodeMeta>, stats: &ChartStats, pin: OutPinId) -> Option<f64> {
+    let values = &stats.process(pin.node).values;
+    let (speed, qty, split) = (values.speed?, values.outputs[pin.output]?, values.output_splits[pin.output].as_ref()?);
+    fits(chart, stats, pin.node, -speed * qty, split).reduce(f64::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{emit, feed, gear_chain, process, process_meta, resource};
+    use crate::model::{Module, Variable};
+    use emath::Pos2;
+
+    #[test]
+    fn balanced_chain() {
+        let (chart, [ore, plate, gear, smelter, assembler]) = gear_chain();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        let plate = stats.resource(plate);
+        assert_eq!((plate.inc, plate.dec, plate.net), (2., 2., 0.));
+        assert_eq!(stats.resource(ore).net, -2.);
+        assert_eq!(stats.resource(gear).net, 1.);
+        assert!(matches!(stats.process(smelter).status, ProcessStatus::Balanced));
+        assert_eq!(stats.process(smelter).machines, 2.);
+        assert_eq!(*stats.process(assembler).input_rates, [2.]);
+        assert_eq!(*stats.process(assembler).output_rates, [1.]);
+    }
+
+    #[test]
+    fn activity_and_capacity_status() {
+        let (mut chart, [_, plate, _, smelter, assembler]) = gear_chain();
+        process_meta(&mut chart, smelter).activity = "2.5".to_owned();
+        process_meta(&mut chart, assembler).capacity = "3".to_owned();
+        process_meta(&mut chart, assembler).activity = "1.5".to_owned();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(smelter).status, ProcessStatus::Deficient));
+        assert!(matches!(stats.process(assembler).status, ProcessStatus::Excess));
+        assert_eq!(stats.process(assembler).machines, 2.);
+        let plate = stats.resource(plate);
+        assert_eq!((plate.net, plate.overproduction), (-1., 0.));
+    }
+
+    #[test]
+    fn variables_and_references() {
+        let (mut chart, [_, plate, _, smelter, assembler]) = gear_chain();
+        process_meta(&mut chart, smelter).speed = "k".to_owned();
+        process_meta(&mut chart, assembler).capacity = "3".to_owned();
+        process_meta(&mut chart, assembler).activity = r#"rate("Smelter") / 2"#.to_owned();
+        let env = Env::new(&[Variable { name: "k".to_owned(), expr: "3".to_owned() }]);
+        let stats = ChartStats::compute(&chart, &env);
+        assert_eq!(stats.process(assembler).values.activity, Some(3.));
+        assert_eq!(stats.resource(plate).net, 0.);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let (mut chart, [_, plate, gear, smelter, assembler]) = gear_chain();
+        process_meta(&mut chart, smelter).capacity = "1 +".to_owned();
+        process_meta(&mut chart, assembler).activity = r#"activity("Assembler")"#.to_owned();
+        let NodeMeta::Resource(meta) = &mut chart[gear] else { unreachable!() };
+        (meta.use_base_rate, meta.base_rate) = (true, "undefined".to_owned());
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(smelter).status, ProcessStatus::Invalid));
+        assert!(matches!(stats.process(assembler).status, ProcessStatus::Invalid));
+        assert!(stats.resource(gear).invalid);
+        let plate = stats.resource(plate);
+        assert!(!plate.invalid);
+        assert_eq!((plate.inc, plate.dec), (0., 0.));
+    }
+
+    #[test]
+    fn reference_cycles() {
+        for resources_first in [false, true] {
+            let mut chart = Snarl::new();
+            let resources = |chart: &mut Snarl<NodeMeta>| [resource(chart, "Ore"), resource(chart, "X")];
+            let processes =
+                |chart: &mut Snarl<NodeMeta>| [process(chart, "A", "1", "1", &[], &["1"]), process(chart, "B", r#"net("Ore")"#, "1", &[], &["1"])];
+            let ([ore, x], [a, b]) = match resources_first {
+                true => (resources(&mut chart), processes(&mut chart)),
+                false => {
+                    let processes = processes(&mut chart);
+                    (resources(&mut chart), processes)
+                }
+            };
+            let NodeMeta::Resource(meta) = &mut chart[ore] else { unreachable!() };
+            (meta.use_base_rate, meta.base_rate) = (true, "2".to_owned());
+            emit(&mut chart, a, 0, x);
+            process_meta(&mut chart, a).activity = r#"inc("X")"#.to_owned();
+            let stats = ChartStats::compute(&chart, &Env::new(&[]));
+            assert!(stats.resource(x).invalid);
+            assert!(matches!(stats.process(a).status, ProcessStatus::Invalid));
+            assert_eq!(stats.process(b).values.capacity, Some(2.));
+        }
+    }
+
+    #[test]
+    fn unconnected_pins() {
+        let mut chart = Snarl::new();
+        let plate = resource(&mut chart, "Plate");
+        let p = process(&mut chart, "Assembler", "1", "1", &["2", "1"], &["1"]);
+        feed(&mut chart, plate, p, 0);
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(p).status, ProcessStatus::Invalid));
+        assert_eq!(*stats.process(p).input_rates, [2., 0.]);
+        assert_eq!(stats.resource(plate).dec, 2.);
+        assert_eq!(fit_activity_to_input(&chart, &stats, InPinId { node: p, input: 1 }), None);
+        assert_eq!(fit_activity_to_output(&chart, &stats, OutPinId { node: p, output: 0 }), None);
+    }
+
+    #[test]
+    fn split_output() {
+        let mut chart = Snarl::new();
+        let (a, b) = (resource(&mut chart, "A"), resource(&mut chart, "B"));
+        let p = process(&mut chart, "Splitter", "1", "1", &[], &["3"]);
+        emit(&mut chart, p, 0, a);
+        emit(&mut chart, p, 0, b);
+        process_meta(&mut chart, p).output_weights = vec![BTreeMap::from([(a, "2".to_owned())])];
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(p).status, ProcessStatus::Balanced));
+        assert_eq!(*stats.process(p).output_rates, [3.]);
+        assert_eq!((stats.resource(a).inc, stats.resource(b).inc), (2., 1.));
+        assert_eq!(stats.wire_rate(&chart, OutPinId { node: p, output: 0 }, InPinId { node: b, input: 0 }), Some(1.));
+        process_meta(&mut chart, p).output_weights[0].insert(b, "-1".to_owned());
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(p).status, ProcessStatus::Invalid));
+        assert_eq!(stats.resource(a).inc, 0.);
+    }
+
+    #[test]
+    fn output_chances() {
+        let mut chart = Snarl::new();
+        let (a, b) = (resource(&mut chart, "A"), resource(&mut chart, "B"));
+        let p = process(&mut chart, "Centrifuge", "1", "2", &[], &["1", "1"]);
+        emit(&mut chart, p, 0, a);
+        emit(&mut chart, p, 1, b);
+        process_meta(&mut chart, p).output_chances = vec![Chance { probability: "0.5".to_owned(), max: "3".to_owned() }];
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(p).status, ProcessStatus::Balanced));
+        assert_eq!((stats.resource(a).inc, stats.resource(b).inc), (2., 2.));
+        assert!(!stats.process(p).values.output_yields[0].unwrap().is_certain());
+        process_meta(&mut chart, p).output_chances.push(Chance { probability: "2".to_owned(), max: String::new() });
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(p).status, ProcessStatus::Invalid));
+    }
+
+    #[test]
+    fn catalysts() {
+        let mut chart = Snarl::new();
+        let (u235, u238) = (resource(&mut chart, "U-235"), resource(&mut chart, "U-238"));
+        let p = process(&mut chart, "Kovarex", "1", "1", &["40", "5"], &[]);
+        feed(&mut chart, u235, p, 0);
+        feed(&mut chart, u238, p, 1);
+        process_meta(&mut chart, p).input_returns = vec!["41".to_owned(), "2".to_owned()];
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(p).status, ProcessStatus::Balanced));
+        assert_eq!((stats.resource(u235).net, stats.resource(u238).net), (1., -3.));
+        process_meta(&mut chart, p).modules = vec![Module { productivity: "0.5".to_owned(), ..<_>::default() }];
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!((stats.resource(u235).net, stats.resource(u238).net), (1.5, -3.));
+        let NodeMeta::Resource(meta) = &mut chart[u238] else { unreachable!() };
+        (meta.use_base_rate, meta.base_rate) = (true, "6".to_owned());
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(fit_activity_to_input(&chart, &stats, InPinId { node: p, input: 1 }), Some(2.));
+    }
+
+    #[test]
+    fn modules_and_beacons() {
+        let (mut chart, [_, plate, gear, _, assembler]) = gear_chain();
+        let meta = process_meta(&mut chart, assembler);
+        let module = Module { count: "2".to_owned(), speed: "0.25".to_owned(), productivity: "0.1".to_owned(), power: "0.4".to_owned() };
+        (meta.modules, meta.beacons, meta.power) = (vec![module], vec![Module { speed: "0.5".to_owned(), ..<_>::default() }], "1".to_owned());
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        let values = &stats.process(assembler).values;
+        assert!(values.effects == Some(Effects { speed: 2., productivity: 1.2, power: 1.8 }));
+        assert_eq!((stats.resource(plate).dec, stats.resource(gear).inc, stats.process(assembler).power), (4., 2.4, 1.8));
+        assert_eq!(fit_activity_to_input(&chart, &stats, InPinId { node: assembler, input: 0 }), Some(0.5));
+        process_meta(&mut chart, assembler).beacons[0].speed = "-3".to_owned();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(stats.process(assembler).values.speed, Some(0.2));
+    }
+
+    #[test]
+    fn group_flows() {
+        let (chart, [ore, plate, gear, smelter, assembler]) = gear_chain();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        let flows = stats.group_flows(&chart, &BTreeSet::from([smelter, assembler]));
+        assert_eq!(Vec::from_iter(flows), [(ore, Some(-2.)), (gear, Some(1.))]);
+        let flows = stats.group_flows(&chart, &BTreeSet::from([ore, smelter, plate]));
+        assert_eq!(Vec::from_iter(flows), [(ore, Some(-2.)), (plate, Some(2.))]);
+    }
+
+    #[test]
+    fn subfactory() {
+        let (mut child, [ore, _, gear, smelter, _]) = gear_chain();
+        process_meta(&mut child, smelter).power = "1".to_owned();
+        let mut chart = Snarl::new();
+        let (outer_ore, outer_gear) = (resource(&mut chart, "Ore"), resource(&mut chart, "Gear"));
+        let meta = SubfactoryMeta { label: "Gears".to_owned(), scale: "3".to_owned(), chart: child, groups: Vec::new(), pins: None };
+        assert_eq!(meta.boundary(), [vec![ore], vec![gear]]);
+        let node = chart.insert_node(Pos2::ZERO, NodeMeta::Subfactory(Box::new(meta)));
+        feed(&mut chart, outer_ore, node, 0);
+        emit(&mut chart, node, 0, outer_gear);
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!(matches!(stats.process(node).status, ProcessStatus::Balanced));
+        assert_eq!((stats.resource(outer_ore).dec, stats.resource(outer_gear).inc, stats.power), (6., 3., 6.));
+        assert_eq!(fit_activity_to_output(&chart, &stats, OutPinId { node, output: 0 }), Some(0.));
+        let stale = (OutPinId { node: outer_ore, output: 0 }, InPinId { node, input: 1 });
+        _ = chart.connect(stale.0, stale.1);
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(stats.wire_rate(&chart, stale.0, stale.1), None);
+    }
+
+    #[test]
+    fn power_draw() {
+        let (mut chart, [_, _, _, smelter, assembler]) = gear_chain();
+        let meta = process_meta(&mut chart, smelter);
+        (meta.activity, meta.power, meta.idle_power) = ("1.5".to_owned(), "3".to_owned(), "1".to_owned());
+        let meta = process_meta(&mut chart, assembler);
+        (meta.power, meta.locked) = ("2".to_owned(), true);
+        let grid = resource(&mut chart, "Grid");
+        let NodeMeta::Resource(meta) = &mut chart[grid] else { unreachable!() };
+        meta.power = true;
+        let generator = process(&mut chart, "Generator", "10", "1", &[], &["1"]);
+        emit(&mut chart, generator, 0, grid);
+        process_meta(&mut chart, generator).activity = "0".to_owned();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!((stats.process(smelter).power, stats.power), (5., 7.));
+        assert_eq!(stats.resource(grid).net, -7.);
+        crate::balance::balance_all(&mut chart, &Env::new(&[]));
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert!((stats.process(generator).values.activity.unwrap() - 8.).abs() < 1E-6);
+        assert!(stats.resource(grid).net.abs() < 1E-6);
+    }
+
+    #[test]
+    fn fit_activity() {
+        let (mut chart, [_, _, _, smelter, assembler]) = gear_chain();
+        process_meta(&mut chart, assembler).speed = "0.5".to_owned();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(fit_activity_to_input(&chart, &stats, InPinId { node: assembler, input: 0 }), Some(2.));
+        assert_eq!(fit_activity_to_output(&chart, &stats, OutPinId { node: smelter, output: 0 }), Some(1.));
+        process_meta(&mut chart, assembler).speed = "x".to_owned();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(fit_activity_to_input(&chart, &stats, InPinId { node: assembler, input: 0 }), None);
+    }
+
+    #[test]
+    fn fit_activity_to_split() {
+        let (mut chart, [_, plate, _, smelter, _]) = gear_chain();
+        let scrap = resource(&mut chart, "Scrap");
+        let NodeMeta::Resource(meta) = &mut chart[scrap] else { unreachable!() };
+        (meta.use_base_rate, meta.base_rate) = (true, "-1".to_owned());
+        emit(&mut chart, smelter, 0, scrap);
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(fit_activity_to_output(&chart, &stats, OutPinId { node: smelter, output: 0 }), Some(4.));
+        let p = process(&mut chart, "Recycler", "1", "1", &["1"], &[]);
+        feed(&mut chart, plate, p, 0);
+        feed(&mut chart, scrap, p, 0);
+        process_meta(&mut chart, smelter).capacity = "6".to_owned();
+        let stats = ChartStats::compute(&chart, &Env::new(&[]));
+        assert_eq!(fit_activity_to_input(&chart, &stats, InPinId { node: p, input: 0 }), Some(2.));
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+
+/// Persistent storage of charts by name and of the recipe library.
+pub trait Storage {
+    /// Names of the saved charts.
+    fn list(&self) -> Result<Vec<String>>;
+    fn load(&self, name: &str) -> Result<String>;
+    fn save(&self, name: &str, data: &str) -> Result<()>;
+    fn delete(&self, name: &str) -> Result<()>;
+    fn load_library(&self) -> Option<String>;
+    fn save_library(&self, data: &str) -> Result<()>;
+}
+
+#[cfg(target_arch = "wasm32")]
+pub use web::open;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::open;
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    use super::Storage;
+    use anyhow::{Context as _, Result, anyhow};
+    use web_sys::window;
+
+    const PREFIX: &str = "factory-balancer/";
+    /// Outside of the chart prefix so it doesn't show up in the chart list.
+    const LIBRARY_KEY: &str = "factory-balancer-library";
+
+    /// Browser local storage.
+    struct WebStorage(web_sys::Storage);
+
+    pub fn open() -> Option<Box<dyn Storage>> { Some(Box::new(WebStorage(window().unwrap().local_storage().ok()??))) }
+
+    impl Storage for WebStorage {
+        fn list(&self) -> Result<Vec<String>> {
+            let len = self.0.length().map_err(|e| anyhow!("{e:?}"))?;
+            let mut names = Vec::new();
+            for i in 0..len {
+                let key = self.0.key(i).ok().flatten().context("Failed to list storage keys")?;
+                let Some(name) = key.strip_prefix(PREFIX) else { continue };
+                let false = name.is_empty() else { continue };
+                names.push(name.to_owned());
+            }
+            Ok(names)
+        }
+
+        fn load(&self, name: &str) -> Result<String> { self.0.get_item(&format!("{PREFIX}{name}")).ok().flatten().context("Item not found") }
+        fn save(&self, name: &str, data: &str) -> Result<()> { self.0.set_item(&format!("{PREFIX}{name}"), data).map_err(|e| anyhow!("{e:?}")) }
+        fn delete(&self, name: &str) -> Result<()> { self.0.remove_item(&format!("{PREFIX}{name}")).map_err(|e| anyhow!("{e:?}")) }
+        fn load_library(&self) -> Option<String> { self.0.get_item(LIBRARY_KEY).ok().flatten() }
+        fn save_library(&self, data: &str) -> Result<()> { self.0.set_item(LIBRARY_KEY, data).map_err(|e| anyhow!("{e:?}")) }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use super::Storage;
+    use anyhow::{Context as _, Result};
+    use std::{env, fs, io::ErrorKind, path::PathBuf};
+
+    /// Files in a data directory, with each chart in `charts/<name>.ron`.
+    struct FileStorage {
+        dir: PathBuf,
+    }
+
+    /// Use `$XDG_DATA_HOME/factory-balancer`, defaulting to `~/.local/share/factory-balancer`.
+    pub fn open() -> Option<Box<dyn Storage>> {
+        let base = env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| Some(env::home_dir()?.join(".local/share")))?;
+        Some(Box::new(FileStorage { dir: base.join("factory-balancer") }))
+    }
+
+    /// Percent-encode characters that aren't allowed in file names.
+    fn escape(name: &str) -> String {
+        let mut result = String::new();
+        for (i, c) in name.char_indices() {
+            if c.is_control() || "%/\\:*?\"<>|".contains(c) || (i == 0 && c == '.') {
+                c.encode_utf8(&mut [0; 4]).bytes().for_each(|x| result += &format!("%{x:02X}"));
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    fn unescape(name: &str) -> Option<String> {
+        let mut bytes = Vec::new();
+        let mut rest = name.as_bytes();
+        while let Some((&x, after)) = rest.split_first() {
+            if x == b'%' {
+                bytes.push(u8::from_str_radix(str::from_utf8(after.get(..2)?).ok()?, 16).ok()?);
+                rest = &after[2..];
+            } else {
+                bytes.push(x);
+                rest = after;
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+
+    impl FileStorage {
+        fn chart_path(&self, name: &str) -> PathBuf { self.dir.join("charts").join(format!("{}.ron", escape(name))) }
+        fn library_path(&self) -> PathBuf { self.dir.join("library.ron") }
+    }
+
+    impl Storage for FileStorage {
+        fn list(&self) -> Result<Vec<String>> {
+            let entries = match fs::read_dir(self.dir.join("charts")) {
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                x => x?,
+            };
+            let mut names = Vec::new();
+            for entry in entries {
+                let Some(name) = entry?.file_name().to_str().and_then(|x| unescape(x.strip_suffix(".ron")?)) else { continue };
+                names.push(name);
+            }
+            Ok(names)
+        }
+
+        fn load(&self, name: &str) -> Result<String> {
+            let path = self.chart_path(name);
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))
+        }
+
+        fn save(&self, name: &str, data: &str) -> Result<()> {
+            let path = self.chart_path(name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            Ok(fs::write(path, data)?)
+        }
+
+        fn delete(&self, name: &str) -> Result<()> { Ok(fs::remove_file(self.chart_path(name))?) }
+        fn load_library(&self) -> Option<String> { fs::read_to_string(self.library_path()).ok() }
+
+        fn save_library(&self, data: &str) -> Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            Ok(fs::write(self.library_path(), data)?)
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::viewer::node_fill;
+use eframe::egui::{Color32, Grid, RichText, ScrollArea, TextEdit, Ui};
+use egui_snarl::{NodeId, Snarl};
+use factory_balancer::THRESHOLD;
+use factory_balancer::format::{RateFormat, format_float};
+use factory_balancer::model::NodeMeta;
+use factory_balancer::stats::{ChartStats, NodeStats, ProcessStatus};
+use std::cmp::Ordering;
+
+enum Key {
+    Text(String),
+    Number(f64),
+}
+
+impl Key {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a.total_cmp(b),
+            (Self::Text(a), Self::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+struct Row {
+    node: NodeId,
+    /// Text and sort key of each column.
+    cells: Vec<(String, Key)>,
+    color: Option<Color32>,
+}
+
+/// Column to sort by, and whether descending.
+#[derive(Default)]
+struct Sort(usize, bool);
+
+/// Tables of every resource and process, sortable by clicking the headers.
+#[derive(Default)]
+pub struct Summary {
+    filter: String,
+    problems_only: bool,
+    resource_sort: Sort,
+    process_sort: Sort,
+}
+
+fn number(x: Option<f64>) -> (String, Key) { (x.map_or("?".to_owned(), |x| format_float(x, THRESHOLD)), Key::Number(x.unwrap_or(f64::NAN))) }
+fn rate(fmt: &RateFormat, x: Option<f64>) -> (String, Key) { (x.map_or("?".to_owned(), |x| fmt.format(x)), Key::Number(x.unwrap_or(f64::NAN))) }
+fn text(x: &str) -> (String, Key) { (x.to_owned(), Key::Text(x.to_owned())) }
+
+/// Return the node of the clicked row.
+fn show_table(ui: &mut Ui, id: &str, headers: &[&str], sort: &mut Sort, mut rows: Vec<Row>) -> Option<NodeId> {
+    rows.sort_by(|a, b| {
+        let ord = a.cells[sort.0].1.cmp(&b.cells[sort.0].1);
+        if sort.1 { ord.reverse() } else { ord }
+    });
+    let mut clicked = None;
+    Grid::new(id).striped(true).show(ui, |ui| {
+        for (i, header) in headers.iter().enumerate() {
+            let arrow = if sort.0 != i {
+                ""
+            } else if sort.1 {
+                " ⏷"
+            } else {
+                " ⏶"
+            };
+            if ui.selectable_label(sort.0 == i, format!("{header}{arrow}")).clicked() {
+                *sort = Sort(i, sort.0 == i && !sort.1);
+            }
+        }
+        ui.end_row();
+        for row in rows {
+            let mut cells = row.cells.into_iter();
+            let label = cells.next().unwrap().0;
+            ui.selectable_label(false, if label.is_empty() { "(unnamed)" } else { &label }).clicked().then(|| clicked = Some(row.node));
+            for (text, _) in cells {
+                ui.label(row.color.map_or(RichText::new(&text), |x| RichText::new(&text).color(x)));
+            }
+            ui.end_row();
+        }
+    });
+    clicked
+}
+
+impl Summary {
+    /// Return the node of the clicked row.
+    pub fn show(&mut self, ui: &mut Ui, chart: &Snarl<NodeMeta>, stats: &ChartStats, fmt: &RateFormat) -> Option<NodeId> {
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            TextEdit::singleline(&mut self.filter).desired_width(120.).show(ui);
+            ui.checkbox(&mut self.problems_only, "Problems Only");
+        });
+        let filter = self.filter.to_lowercase();
+        let (mut resources, mut processes) = (Vec::new(), Vec::new());
+        for (node, meta) in chart.node_ids() {
+            let color = stats.nodes.get(&node).and_then(node_fill);
+            let false = (self.problems_only && color.is_none()) else { continue };
+            match meta {
+                NodeMeta::Resource(meta) => {
+                    let true = meta.label.to_lowercase().contains(&filter) else { continue };
+                    let r = stats.resource(node);
+                    let base_rate = meta.use_base_rate.then_some(r.base_rate);
+                    let valid = |x| (!r.invalid).then_some(x);
+                    let cells =
+                        vec![text(&meta.label), rate(fmt, valid(r.inc)), rate(fmt, valid(r.dec)), rate(fmt, valid(r.net)), rate(fmt, base_rate)];
+                    resources.push(Row { node, cells, color });
+                }
+                NodeMeta::Process(_) | NodeMeta::Subfactory(_) => {
+                    let label = meta.label();
+                    let true = label.to_lowercase().contains(&filter) else { continue };
+                    let Some(NodeStats::Process(p)) = stats.nodes.get(&node) else { continue };
+                    let status = match p.status {
+                        ProcessStatus::Invalid => "Invalid",
+                        ProcessStatus::Balanced => "Balanced",
+                        ProcessStatus::Deficient => "Deficient",
+                        ProcessStatus::Excess => "Excess",
+                    };
+                    let cells = vec![
+                        text(label),
+                        number(p.values.capacity),
+                        number(p.values.activity),
+                        rate(fmt, p.values.common_rate().map(|x| x.0)),
+                        text(status),
+                    ];
+                    processes.push(Row { node, cells, color });
+                }
+            }
+        }
+        let mut clicked = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            ui.heading("Resources");
+            let headers = ["Resource", "Inc", "Dec", "Net", "Base"];
+            clicked = show_table(ui, "summary_resources", &headers, &mut self.resource_sort, resources);
+            ui.heading("Processes");
+            let headers = ["Process", "Cap", "Act", "Rate", "Status"];
+            clicked = show_table(ui, "summary_processes", &headers, &mut self.process_sort, processes).or(clicked);
+        });
+        clicked
+    }
+}
@@ -0,0 +1,585 @@
+use crate::groups::{color, frame};
+use eframe::egui::epaint::CubicBezierShape;
+use eframe::egui::{
+    Align, Align2, Color32, FontId, Frame, Grid, Layout, Painter, PointerButton, Popup, Pos2, Rect, RectAlign, Stroke, StrokeKind, Style, TextEdit,
+    Ui, Vec2, vec2,
+};
+use eframe::emath::TSTransform;
+use egui_snarl::ui::{BackgroundPattern, PinInfo, PinWireInfo, SnarlPin, SnarlStyle, SnarlViewer, WireStyle};
+use egui_snarl::{InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
+use factory_balancer::THRESHOLD;
+use factory_balancer::format::{RateFormat, format_float};
+use factory_balancer::library::Library;
+use factory_balancer::model::{Chance, Group, Module, NodeMeta, ProcessMeta, ResourceMeta, SubfactoryMeta};
+use factory_balancer::stats::{ChartStats, Effects, NodeStats, ProcessStatus, ResourceStats, Split};
+use std::collections::{BTreeMap, HashMap};
+use std::{cell::RefCell, mem::take, rc::Rc};
+
+const TOOLTIP_WIDTH: f32 = 160.;
+const BROWN: Color32 = Color32::from_rgb(160, 80, 0);
+/// Also set in the style so that flow bands follow the wires.
+pub const WIRE_FRAME_SIZE: f32 = 36.;
+const MAX_FLOW_WIDTH: f32 = 24.;
+
+pub enum Action {
+    None,
+    AddInput(NodeId),
+    AddOutput(NodeId),
+    RemoveInput(InPinId),
+    RemoveOutput(OutPinId),
+    FitActivityToInput(InPinId),
+    FitActivityToOutput(OutPinId),
+    Duplicate(NodeId),
+    AddToLibrary(NodeId),
+    Delete(NodeId),
+    /// Open the chart of a subfactory.
+    Open(NodeId),
+    Group(Vec<NodeId>),
+    Ungroup(NodeId),
+}
+
+pub struct ChartViewer<'a> {
+    pub action: Action,
+    pub library: &'a Library,
+    pub connect_resources: bool,
+    pub to_global: TSTransform,
+    /// Pan the view to bring a position in graph space to a position on screen.
+    pub pan: Option<(Pos2, Pos2)>,
+    pub stats: ChartStats,
+    pub integer_mode: bool,
+    pub fmt: RateFormat,
+    /// Draw a band behind each wire with a width proportional to its rate.
+    pub flow_wires: bool,
+    pub pins: PinPositions,
+    /// Pin positions the bands were drawn with. They lag a frame behind, so repaint if the pins moved since.
+    pub drawn_pins: HashMap<Pin, Pos2>,
+    pub groups: &'a [Group],
+    pub selected: Vec<NodeId>,
+    /// Rect of each node relative to its position, as last drawn.
+    pub node_rects: HashMap<NodeId, Rect>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pin {
+    In(InPinId),
+    Out(OutPinId),
+}
+
+/// Where each pin was last drawn, in graph space.
+pub type PinPositions = Rc<RefCell<HashMap<Pin, Pos2>>>;
+
+/// Records its position when drawn.
+struct TrackedPin {
+    info: PinInfo,
+    pin: Pin,
+    positions: PinPositions,
+}
+
+impl SnarlPin for TrackedPin {
+    fn draw(self, snarl_style: &SnarlStyle, style: &Style, rect: Rect, painter: &Painter) -> PinWireInfo {
+        self.positions.borrow_mut().insert(self.pin, rect.center());
+        self.info.draw(snarl_style, style, rect, painter)
+    }
+}
+
+pub fn node_fill(stats: &NodeStats) -> Option<Color32> {
+    match stats {
+        NodeStats::Process(stats) => match stats.status {
+            ProcessStatus::Invalid => Some(Color32::DARK_RED),
+            ProcessStatus::Balanced => None,
+            ProcessStatus::Deficient => Some(BROWN),
+            ProcessStatus::Excess => Some(Color32::DARK_GREEN),
+        },
+        NodeStats::Resource(stats) => resource_fill(stats),
+    }
+}
+
+/// Red if invalid, brown in deficit and green in surplus.
+pub fn resource_fill(stats: &ResourceStats) -> Option<Color32> {
+    if stats.invalid {
+        Some(Color32::DARK_RED)
+    } else if stats.net < -THRESHOLD {
+        Some(BROWN)
+    } else if stats.net > THRESHOLD {
+        Some(Color32::DARK_GREEN)
+    } else {
+        None
+    }
+}
+
+fn resource_label(chart: &Snarl<NodeMeta>, node: NodeId) -> &str {
+    let NodeMeta::Resource(meta) = &chart[node] else { unreachable!() };
+    if meta.label.is_empty() { "?" } else { &meta.label }
+}
+
+/// Edit the weights splitting a pin among its resources, only storing the ones that are changed.
+fn show_weights(ui: &mut Ui, title: &str, remotes: Vec<(NodeId, String)>, weights: &mut Vec<BTreeMap<NodeId, String>>, pin: usize) {
+    ui.label(title);
+    for (r, label) in remotes {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            let mut text = weights.get(pin).and_then(|x| x.get(&r)).cloned().unwrap_or_else(|| "1".to_owned());
+            if TextEdit::singleline(&mut text).desired_width(40.).show(ui).response.changed() {
+                weights.resize_with(weights.len().max(pin + 1), <_>::default);
+                weights[pin].insert(r, text);
+            }
+        });
+    }
+}
+
+/// Edit the amount of each input given back per craft.
+fn show_returns(ui: &mut Ui, returns: &mut Vec<String>, inputs: usize) {
+    Grid::new("returns").show(ui, |ui| {
+        ["Input", "Returned"].into_iter().for_each(|x| drop(ui.label(x)));
+        ui.end_row();
+        for input in 0..inputs {
+            ui.label(format!("{}", input + 1));
+            let mut text = returns.get(input).cloned().unwrap_or_default();
+            if TextEdit::singleline(&mut text).desired_width(60.).show(ui).response.changed() {
+                returns.resize_with(returns.len().max(input + 1), <_>::default);
+                returns[input] = text;
+            }
+            ui.end_row();
+        }
+    });
+}
+
+/// Edit the probability and maximum amount of each output, only storing the ones that are changed.
+fn show_chances(ui: &mut Ui, chances: &mut Vec<Chance>, outputs: usize) {
+    Grid::new("chances").show(ui, |ui| {
+        ["Output", "Probability", "Max"].into_iter().for_each(|x| drop(ui.label(x)));
+        ui.end_row();
+        for output in 0..outputs {
+            ui.label(format!("{}", output + 1));
+            let mut chance = chances.get(output).cloned().unwrap_or_default();
+            let probability = TextEdit::singleline(&mut chance.probability).desired_width(60.).show(ui).response.changed();
+            if TextEdit::singleline(&mut chance.max).desired_width(40.).show(ui).response.changed() || probability {
+                chances.resize_with(chances.len().max(output + 1), <_>::default);
+                chances[output] = chance;
+            }
+            ui.end_row();
+        }
+    });
+}
+
+/// Edit the count and effects of each kind of module or beacon, one row each.
+fn show_modules(ui: &mut Ui, title: &str, modules: &mut Vec<Module>) {
+    ui.horizontal(|ui| {
+        ui.label(title);
+        ui.small_button("➕").clicked().then(|| modules.push(<_>::default()));
+    });
+    let false = modules.is_empty() else { return };
+    let mut removed = None;
+    Grid::new(title).show(ui, |ui| {
+        ["Count", "Speed", "Prod", "Power"].into_iter().for_each(|x| drop(ui.label(x)));
+        ui.end_row();
+        for (i, x) in modules.iter_mut().enumerate() {
+            for text in [&mut x.count, &mut x.speed, &mut x.productivity, &mut x.power] {
+                TextEdit::singleline(text).desired_width(40.).show(ui);
+            }
+            ui.small_button("✖").clicked().then(|| removed = Some(i));
+            ui.end_row();
+        }
+    });
+    removed.map(|i| modules.remove(i));
+}
+
+impl ChartViewer<'_> {
+    /// Rate along a wire and the color of its resource.
+    fn wire_flow(&self, chart: &Snarl<NodeMeta>, from: OutPinId, to: InPinId) -> Option<(f64, Color32)> {
+        let resource = if let NodeMeta::Resource(_) = &chart[from.node] { from.node } else { to.node };
+        Some((self.stats.wire_rate(chart, from, to)?, resource_fill(&self.stats.resource(resource)).unwrap_or(Color32::GRAY)))
+    }
+
+    fn pin(&self, pin: Pin) -> TrackedPin {
+        let mut info = PinInfo::square();
+        self.flow_wires.then(|| info.wire_style = Some(WireStyle::Bezier3));
+        TrackedPin { info, pin, positions: self.pins.clone() }
+    }
+
+    /// List the rate of each wire of a pin, or just the pin's rate if it's unwired or the split is invalid.
+    fn pin_tooltip(&self, chart: &Snarl<NodeMeta>, split: Option<&Option<Split>>, rate: Option<&f64>) -> String {
+        let Some(&rate) = rate else { return String::new() };
+        match split {
+            Some(Some(split)) if split.len() == 1 => format!("{}: {}", resource_label(chart, split[0].0), self.fmt.format(rate)),
+            Some(Some(split)) => {
+                let lines =
+                    split.iter().map(|&(r, share)| format!("{} ({:.1}%): {}", resource_label(chart, r), share * 100., self.fmt.format(rate * share)));
+                Vec::from_iter(lines).join("\n")
+            }
+            _ => self.fmt.format(rate),
+        }
+    }
+}
+
+pub fn prepare_small_button(ui: &mut Ui) {
+    let spacing = &mut ui.style_mut().spacing;
+    spacing.button_padding = Vec2::ZERO;
+    spacing.item_spacing = vec2(1., 0.);
+}
+
+impl SnarlViewer<NodeMeta> for ChartViewer<'_> {
+    fn connect(&mut self, from: &OutPin, to: &InPin, chart: &mut Snarl<NodeMeta>) {
+        _ = match (&chart[from.id.node], &chart[to.id.node]) {
+            (NodeMeta::Resource(_), NodeMeta::Resource(_)) => false,
+            (NodeMeta::Resource(_), _) | (_, NodeMeta::Resource(_)) => chart.connect(from.id, to.id),
+            _ => false,
+        };
+    }
+
+    fn title(&mut self, meta: &NodeMeta) -> String {
+        match meta {
+            NodeMeta::Resource(meta) => meta.label.clone(),
+            NodeMeta::Process(meta) => meta.label.clone(),
+            NodeMeta::Subfactory(meta) => meta.label.clone(),
+        }
+    }
+
+    fn show_header(&mut self, node: NodeId, _: &[InPin], _: &[OutPin], ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
+        let (width, label) = match &mut chart[node] {
+            NodeMeta::Resource(meta) => (80., &mut meta.label),
+            NodeMeta::Process(meta) => {
+                let mut width = 108.;
+                (!meta.inputs.is_empty()).then(|| width += 36.);
+                (!meta.outputs.is_empty()).then(|| width += 36.);
+                (width, &mut meta.label)
+            }
+            NodeMeta::Subfactory(meta) => (228., &mut meta.label),
+        };
+        ui.set_width(width);
+        TextEdit::singleline(label).desired_width(f32::INFINITY).show(ui);
+    }
+
+    fn node_frame(&mut self, mut frame: Frame, node: NodeId, _: &[InPin], _: &[OutPin], _: &Snarl<NodeMeta>) -> Frame {
+        self.stats.nodes.get(&node).and_then(node_fill).into_iter().for_each(|x| frame.fill = x);
+        frame
+    }
+
+    fn has_body(&mut self, _: &NodeMeta) -> bool { true }
+    fn show_body(&mut self, node: NodeId, _: &[InPin], _: &[OutPin], ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
+        match &mut chart[node] {
+            NodeMeta::Resource(meta) => {
+                ui.set_width(72.);
+                ui.vertical_centered(|ui| {
+                    meta.use_base_rate.then(|| TextEdit::singleline(&mut meta.base_rate).desired_width(f32::INFINITY).show(ui));
+                    let stats = self.stats.resource(node);
+                    let inc = self.fmt.format(stats.inc);
+                    let dec = self.fmt.format(stats.dec);
+                    let net = self.fmt.format(stats.net);
+                    let mut text = format!("➕ {inc}\n➖ {dec}\nNet {net}");
+                    self.integer_mode.then(|| text += &format!("\nOver {}", self.fmt.format(stats.overproduction)));
+                    ui.label(text);
+                });
+            }
+            NodeMeta::Process(meta) => {
+                ui.set_width(100.);
+                ui.vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Cap");
+                        TextEdit::singleline(&mut meta.capacity).desired_width(f32::INFINITY).show(ui);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Act");
+                        TextEdit::singleline(&mut meta.activity).desired_width(f32::INFINITY).show(ui);
+                    });
+                    if meta.use_duration {
+                        ui.horizontal(|ui| {
+                            ui.label("Dur");
+                            TextEdit::singleline(&mut meta.duration).desired_width(f32::INFINITY).show(ui);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Mul");
+                            TextEdit::singleline(&mut meta.crafting_speed).desired_width(f32::INFINITY).show(ui);
+                        });
+                    } else {
+                        ui.horizontal(|ui| {
+                            ui.label("Spd");
+                            TextEdit::singleline(&mut meta.speed).desired_width(f32::INFINITY).show(ui);
+                        });
+                    }
+                    if self.integer_mode
+                        && let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&node)
+                    {
+                        ui.label(format!("Machines {}", stats.machines));
+                    }
+                    if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&node)
+                        && stats.power != 0.
+                    {
+                        ui.label(format!("Power {}", format_float(stats.power, THRESHOLD)));
+                    }
+                    if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&node)
+                        && let Some(e) = stats.values.effects
+                        && e != Effects::NONE
+                    {
+                        let [speed, productivity, power] = [e.speed, e.productivity, e.power].map(|x| format_float(x, THRESHOLD));
+                        ui.label(format!("Spd ×{speed}\nProd ×{productivity}\nPwr ×{power}"));
+                    }
+                    ui.horizontal(|ui| {
+                        prepare_small_button(ui);
+                        ui.small_button("➕").clicked().then(|| self.action = Action::AddInput(node));
+                        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                            ui.small_button("➕").clicked().then(|| self.action = Action::AddOutput(node));
+                        });
+                    });
+                });
+            }
+            NodeMeta::Subfactory(meta) => {
+                ui.set_width(100.);
+                ui.vertical(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Scale");
+                        TextEdit::singleline(&mut meta.scale).desired_width(f32::INFINITY).show(ui);
+                    });
+                    if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&node)
+                        && stats.power != 0.
+                    {
+                        ui.label(format!("Power {}", format_float(stats.power, THRESHOLD)));
+                    }
+                    ui.weak(format!("{} nodes", meta.chart.nodes().count()));
+                });
+            }
+        }
+    }
+
+    fn final_node_rect(&mut self, node: NodeId, rect: Rect, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
+        self.node_rects.insert(node, rect.translate(-chart.get_node_info(node).unwrap().pos.to_vec2()));
+        let NodeMeta::Subfactory(_) = &chart[node] else { return };
+        let rect = ui.ctx().layer_transform_to_global(ui.layer_id()).map_or(rect, |t| t * rect);
+        let clicked =
+            ui.input(|x| x.pointer.button_double_clicked(PointerButton::Primary) && x.pointer.interact_pos().is_some_and(|x| rect.contains(x)));
+        // Double-clicking a text field selects a word instead.
+        (clicked && !ui.ctx().wants_keyboard_input()).then(|| self.action = Action::Open(node));
+    }
+
+    fn inputs(&mut self, meta: &NodeMeta) -> usize {
+        match meta {
+            NodeMeta::Resource(_) => 1,
+            NodeMeta::Process(meta) => meta.inputs.len(),
+            NodeMeta::Subfactory(meta) => meta.boundary()[0].len(),
+        }
+    }
+
+    fn show_input(&mut self, pin: &InPin, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) -> impl SnarlPin + 'static {
+        if let NodeMeta::Process(meta) = &mut chart[pin.id.node] {
+            ui.vertical(|ui| {
+                TextEdit::singleline(&mut meta.inputs[pin.id.input]).desired_width(20.).show(ui);
+                ui.horizontal(|ui| {
+                    prepare_small_button(ui);
+                    ui.small_button("✖").clicked().then(|| self.action = Action::RemoveInput(pin.id));
+                    ui.small_button("➡").clicked().then(|| self.action = Action::FitActivityToInput(pin.id));
+                });
+            });
+        } else if let NodeMeta::Subfactory(meta) = &chart[pin.id.node] {
+            ui.horizontal(|ui| {
+                prepare_small_button(ui);
+                ui.small_button("➡").clicked().then(|| self.action = Action::FitActivityToInput(pin.id));
+                ui.label(resource_label(&meta.chart, meta.boundary()[0][pin.id.input]));
+            });
+        }
+        if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&pin.id.node)
+            && let Some(pos) = ui.input(|x| x.pointer.hover_pos())
+            && let rect = ui.min_rect().intersect(ui.clip_rect())
+            && let rect = ui.ctx().layer_transform_to_global(ui.layer_id()).map_or(rect, |t| t * rect)
+            && rect.contains(pos)
+        {
+            let mut msg = self.pin_tooltip(chart, stats.values.input_splits.get(pin.id.input), stats.input_rates.get(pin.id.input));
+            if let NodeMeta::Process(meta) = &chart[pin.id.node]
+                && meta.input_returns.get(pin.id.input).is_some_and(|x| !x.is_empty())
+                && let Some(Some(net)) = stats.values.inputs.get(pin.id.input)
+            {
+                msg = format!("Net use {} per craft\n{msg}", format_float(*net, THRESHOLD));
+            }
+            if !msg.is_empty() {
+                Popup::new(ui.id(), ui.ctx().clone(), rect, ui.layer_id()).align(RectAlign::LEFT).show(|ui| {
+                    ui.set_max_width(TOOLTIP_WIDTH);
+                    ui.label(msg);
+                });
+            }
+        }
+        self.pin(Pin::In(pin.id))
+    }
+
+    fn outputs(&mut self, meta: &NodeMeta) -> usize {
+        match meta {
+            NodeMeta::Resource(_) => 1,
+            NodeMeta::Process(meta) => meta.outputs.len(),
+            NodeMeta::Subfactory(meta) => meta.boundary()[1].len(),
+        }
+    }
+
+    fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) -> impl SnarlPin + 'static {
+        if let NodeMeta::Process(meta) = &mut chart[pin.id.node] {
+            ui.set_width(30.);
+            ui.vertical(|ui| {
+                TextEdit::singleline(&mut meta.outputs[pin.id.output]).desired_width(20.).show(ui);
+                ui.horizontal(|ui| {
+                    prepare_small_button(ui);
+                    ui.small_button("⬅").clicked().then(|| self.action = Action::FitActivityToOutput(pin.id));
+                    ui.small_button("✖").clicked().then(|| self.action = Action::RemoveOutput(pin.id));
+                });
+            });
+        } else if let NodeMeta::Subfactory(meta) = &chart[pin.id.node] {
+            ui.horizontal(|ui| {
+                prepare_small_button(ui);
+                ui.label(resource_label(&meta.chart, meta.boundary()[1][pin.id.output]));
+                ui.small_button("⬅").clicked().then(|| self.action = Action::FitActivityToOutput(pin.id));
+            });
+        }
+        if let Some(NodeStats::Process(stats)) = self.stats.nodes.get(&pin.id.node)
+            && let Some(pos) = ui.input(|x| x.pointer.hover_pos())
+            && let rect = ui.min_rect().intersect(ui.clip_rect())
+            && let rect = ui.ctx().layer_transform_to_global(ui.layer_id()).map_or(rect, |t| t * rect)
+            && rect.contains(pos)
+        {
+            let mut msg = self.pin_tooltip(chart, stats.values.output_splits.get(pin.id.output), stats.output_rates.get(pin.id.output));
+            if let Some(Some(x)) = stats.values.output_yields.get(pin.id.output)
+                && !x.is_certain()
+            {
+                let [min, max, expected] = [x.min, x.max, x.expected()].map(|x| format_float(x, THRESHOLD));
+                let amount = if x.min == x.max { min } else { format!("{min}–{max}") };
+                msg = format!("{:.1}% × {amount}, expected {expected}\n{msg}", x.probability * 100.);
+            }
+            if !msg.is_empty() {
+                Popup::new(ui.id(), ui.ctx().clone(), rect, ui.layer_id()).align(RectAlign::RIGHT).show(|ui| {
+                    ui.set_max_width(TOOLTIP_WIDTH);
+                    ui.label(msg);
+                });
+            }
+        }
+        self.pin(Pin::Out(pin.id))
+    }
+
+    fn has_graph_menu(&mut self, _: Pos2, _: &mut Snarl<NodeMeta>) -> bool { true }
+    fn show_graph_menu(&mut self, pos: Pos2, ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
+        ui.button("New Subfactory").clicked().then(|| {
+            let meta = SubfactoryMeta { label: String::new(), scale: "1".to_owned(), chart: Snarl::new(), groups: Vec::new(), pins: None };
+            chart.insert_node(pos, NodeMeta::Subfactory(Box::new(meta)));
+        });
+        ui.button("New Resource").clicked().then(|| {
+            chart.insert_node(
+                pos,
+                NodeMeta::Resource(ResourceMeta {
+                    label: String::new(),
+                    base_rate: String::new(),
+                    use_base_rate: false,
+                    nonnegative: false,
+                    power: false,
+                }),
+            );
+        });
+        ui.button("New Process").clicked().then(|| {
+            let meta = ProcessMeta {
+                capacity: "1".to_owned(),
+                speed: "1".to_owned(),
+                inputs: vec!["1".to_owned()],
+                outputs: vec!["1".to_owned()],
+                ..<_>::default()
+            };
+            chart.insert_node(pos, NodeMeta::Process(Box::new(meta)));
+        });
+        if !self.library.recipes.is_empty() {
+            ui.menu_button("Insert Recipe", |ui| {
+                for recipe in &self.library.recipes {
+                    ui.button(&recipe.process.label).clicked().then(|| recipe.instantiate(chart, pos, self.connect_resources));
+                }
+            });
+        }
+    }
+
+    fn draw_background(
+        &mut self, background: Option<&BackgroundPattern>, viewport: &Rect, snarl_style: &SnarlStyle, style: &Style, painter: &Painter,
+        chart: &Snarl<NodeMeta>,
+    ) {
+        background.inspect(|x| x.draw(viewport, snarl_style, style, painter));
+        for group in self.groups {
+            let Some(rect) = frame(chart, group, &self.node_rects) else { continue };
+            let color = color(group);
+            painter.rect(rect, 6., color.gamma_multiply(0.1), Stroke::new(1.5, color), StrokeKind::Inside);
+        }
+        let true = self.flow_wires else { return };
+        self.drawn_pins = take(&mut *self.pins.borrow_mut());
+        let wires = Vec::from_iter(chart.wires().filter_map(|(from, to)| {
+            let (rate, color) = self.wire_flow(chart, from, to)?;
+            Some((rate, color, *self.drawn_pins.get(&Pin::Out(from))?, *self.drawn_pins.get(&Pin::In(to))?))
+        }));
+        let max = wires.iter().map(|x| x.0.abs()).fold(0., f64::max);
+        for (rate, color, from, to) in wires {
+            // Same curve as `WireStyle::Bezier3`.
+            let frame = WIRE_FRAME_SIZE.min(from.distance(to) / 6.);
+            let points = [from, from + vec2(frame, 0.), to - vec2(frame, 0.), to];
+            let width = if max > THRESHOLD { 1. + MAX_FLOW_WIDTH * (rate.abs() / max) as f32 } else { 1. };
+            let band = CubicBezierShape::from_points_stroke(points, false, Color32::TRANSPARENT, Stroke::new(width, color.gamma_multiply(0.6)));
+            let pos = band.sample(0.5) - vec2(0., width / 2.);
+            painter.add(band);
+            painter.text(pos, Align2::CENTER_BOTTOM, self.fmt.format(rate), FontId::proportional(10.), style.visuals.text_color());
+        }
+    }
+
+    fn current_transform(&mut self, to_global: &mut TSTransform, _: &mut Snarl<NodeMeta>) {
+        if let Some((from, to)) = self.pan.take() {
+            to_global.translation = to.to_vec2() - from.to_vec2() * to_global.scaling;
+        }
+        self.to_global = *to_global
+    }
+
+    fn has_node_menu(&mut self, _: &NodeMeta) -> bool { true }
+    fn show_node_menu(&mut self, node: NodeId, inputs: &[InPin], outputs: &[OutPin], ui: &mut Ui, chart: &mut Snarl<NodeMeta>) {
+        ui.button("Delete").clicked().then(|| self.action = Action::Delete(node));
+        ui.button("Duplicate").clicked().then(|| self.action = Action::Duplicate(node));
+        match &chart[node] {
+            NodeMeta::Process(_) => drop(ui.button("Add to Library").clicked().then(|| self.action = Action::AddToLibrary(node))),
+            NodeMeta::Subfactory(_) => drop(ui.button("Open").clicked().then(|| self.action = Action::Open(node))),
+            NodeMeta::Resource(_) => (),
+        }
+        ui.button("New Group").clicked().then(|| {
+            let nodes = if self.selected.contains(&node) { self.selected.clone() } else { vec![node] };
+            self.action = Action::Group(nodes)
+        });
+        if self.groups.iter().any(|x| x.nodes.contains(&node)) {
+            ui.button("Remove from Group").clicked().then(|| self.action = Action::Ungroup(node));
+        }
+        match &mut chart[node] {
+            NodeMeta::Resource(meta) => {
+                ui.checkbox(&mut meta.use_base_rate, "Enable Base Rate");
+                ui.checkbox(&mut meta.nonnegative, "Require Non-negative Net");
+                ui.checkbox(&mut meta.power, "Supplies Power");
+            }
+            NodeMeta::Process(meta) => {
+                ui.checkbox(&mut meta.locked, "Lock Activity");
+                if ui.checkbox(&mut meta.use_duration, "Use Recipe Duration").changed() && meta.use_duration {
+                    meta.duration.is_empty().then(|| meta.duration = "1".to_owned());
+                    meta.crafting_speed.is_empty().then(|| meta.crafting_speed = "1".to_owned());
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Power");
+                    TextEdit::singleline(&mut meta.power).desired_width(60.).show(ui);
+                    ui.label("Idle");
+                    TextEdit::singleline(&mut meta.idle_power).desired_width(60.).show(ui);
+                });
+                if !meta.inputs.is_empty() {
+                    ui.menu_button("Catalysts", |ui| show_returns(ui, &mut meta.input_returns, meta.inputs.len()));
+                }
+                if !meta.outputs.is_empty() {
+                    ui.menu_button("Output Chances", |ui| show_chances(ui, &mut meta.output_chances, meta.outputs.len()));
+                }
+                show_modules(ui, "Modules", &mut meta.modules);
+                show_modules(ui, "Beacons", &mut meta.beacons);
+            }
+            NodeMeta::Subfactory(_) => (),
+        }
+        // Splits of resources and subfactories are even.
+        let NodeMeta::Process(_) = &chart[node] else { return };
+        let labels =
+            |chart: &Snarl<NodeMeta>, remotes: Vec<NodeId>| Vec::from_iter(remotes.into_iter().map(|r| (r, resource_label(chart, r).to_owned())));
+        for pin in inputs.iter().filter(|x| x.remotes.len() > 1) {
+            let remotes = labels(chart, Vec::from_iter(pin.remotes.iter().map(|x| x.node)));
+            let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
+            show_weights(ui, &format!("Input {} Split", pin.id.input + 1), remotes, &mut meta.input_weights, pin.id.input);
+        }
+        for pin in outputs.iter().filter(|x| x.remotes.len() > 1) {
+            let remotes = labels(chart, Vec::from_iter(pin.remotes.iter().map(|x| x.node)));
+            let NodeMeta::Process(meta) = &mut chart[node] else { unreachable!() };
+            show_weights(ui, &format!("Output {} Split", pin.id.output + 1), remotes, &mut meta.output_weights, pin.id.output);
+        }
+    }
+}